@@ -0,0 +1,206 @@
+use crate::grid::Cell;
+use crossbeam::channel::{unbounded, Receiver, Sender};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::thread;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum AggregateKind {
+    Count,
+    Sum,
+    Min,
+    Max,
+    Avg,
+    Distinct,
+}
+
+impl AggregateKind {
+    // "count sum min max avg distinct", an empty spec turns the footer off
+    pub fn parse_list(spec: &str) -> Result<Vec<AggregateKind>, String> {
+        spec.split_whitespace()
+            .map(|word| match word {
+                "count" => Ok(AggregateKind::Count),
+                "sum" => Ok(AggregateKind::Sum),
+                "min" => Ok(AggregateKind::Min),
+                "max" => Ok(AggregateKind::Max),
+                "avg" => Ok(AggregateKind::Avg),
+                "distinct" => Ok(AggregateKind::Distinct),
+                _ => Err(format!("unknown aggregate {:?}", word)),
+            })
+            .collect()
+    }
+}
+
+// What the footer shows; cheap to send back across the channel, unlike the distinct set.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Summary {
+    pub count: usize, // non-null cells
+    pub numeric: usize,
+    pub sum: f64,
+    pub min: Option<Cell>,
+    pub max: Option<Cell>,
+    pub distinct: usize,
+}
+
+impl Summary {
+    pub fn avg(&self) -> Option<f64> {
+        if self.numeric == 0 {
+            None
+        } else {
+            Some(self.sum / self.numeric as f64)
+        }
+    }
+}
+
+#[derive(Hash, PartialEq, Eq)]
+enum DistinctKey {
+    Int(i64),
+    Float(u64),
+    Text(String),
+    Timestamp(i64),
+    Bytes(Vec<u8>),
+}
+
+struct Accumulator {
+    summary: Summary,
+    seen: HashSet<DistinctKey>,
+}
+
+impl Accumulator {
+    fn new() -> Accumulator {
+        Accumulator {
+            summary: Summary {
+                count: 0,
+                numeric: 0,
+                sum: 0.0,
+                min: None,
+                max: None,
+                distinct: 0,
+            },
+            seen: HashSet::new(),
+        }
+    }
+
+    fn add(&mut self, cell: &Cell) {
+        let key = match cell {
+            Cell::Null => return,
+            Cell::Int(i) => DistinctKey::Int(*i),
+            // so 0.0 and -0.0 count once
+            Cell::Float(f) => DistinctKey::Float((f + 0.0).to_bits()),
+            Cell::Text(s) => DistinctKey::Text(s.clone()),
+            Cell::Timestamp(t) => DistinctKey::Timestamp(*t),
+            Cell::Bytes(b) => DistinctKey::Bytes(b.clone()),
+        };
+        let summary = &mut self.summary;
+        summary.count += 1;
+        match cell {
+            Cell::Int(i) => {
+                summary.numeric += 1;
+                summary.sum += *i as f64;
+            }
+            Cell::Float(f) if !f.is_nan() => {
+                summary.numeric += 1;
+                summary.sum += f;
+            }
+            _ => {}
+        }
+        if replaces(&summary.min, cell, Ordering::Less) {
+            summary.min = Some(cell.clone());
+        }
+        if replaces(&summary.max, cell, Ordering::Greater) {
+            summary.max = Some(cell.clone());
+        }
+        if self.seen.insert(key) {
+            summary.distinct += 1;
+        }
+    }
+}
+
+fn replaces(current: &Option<Cell>, cell: &Cell, wanted: Ordering) -> bool {
+    match current {
+        // NaN never takes the first slot, it would never be displaced
+        None => compare_cells(cell, cell).is_some(),
+        Some(c) => compare_cells(cell, c) == Some(wanted),
+    }
+}
+
+// Only cells of comparable kinds are ordered, so min/max stick to the kind of the first
+// comparable cell in the column.
+fn compare_cells(a: &Cell, b: &Cell) -> Option<Ordering> {
+    match (a, b) {
+        (Cell::Int(x), Cell::Int(y)) => Some(x.cmp(y)),
+        (Cell::Int(x), Cell::Float(y)) => (*x as f64).partial_cmp(y),
+        (Cell::Float(x), Cell::Int(y)) => x.partial_cmp(&(*y as f64)),
+        (Cell::Float(x), Cell::Float(y)) => x.partial_cmp(y),
+        (Cell::Text(x), Cell::Text(y)) => Some(x.cmp(y)),
+        (Cell::Timestamp(x), Cell::Timestamp(y)) => Some(x.cmp(y)),
+        (Cell::Bytes(x), Cell::Bytes(y)) => Some(x.cmp(y)),
+        _ => None,
+    }
+}
+
+// Keeps one accumulator per grid column on its own thread. Shown cells are only ever sent once,
+// as they're pushed, unless Lean changes which rows are shown and the column starts over.
+// Summaries come back when the worker has caught up with its queue.
+pub struct AggregateWorker {
+    jobs: Sender<Job>,
+    results: Receiver<(usize, Summary)>,
+}
+
+struct Job {
+    col_id: usize,
+    cells: Vec<Cell>,
+    restart: bool, // drop what the column had first
+}
+
+impl AggregateWorker {
+    pub fn spawn() -> AggregateWorker {
+        let (jobs, job_rx) = unbounded::<Job>();
+        let (result_tx, results) = unbounded();
+        thread::spawn(move || {
+            let mut columns: HashMap<usize, Accumulator> = HashMap::new();
+            let mut dirty: Vec<usize> = Vec::new();
+            for job in job_rx.iter() {
+                if job.restart {
+                    columns.remove(&job.col_id);
+                }
+                let acc = columns.entry(job.col_id).or_insert_with(Accumulator::new);
+                job.cells.iter().for_each(|cell| acc.add(cell));
+                if !dirty.contains(&job.col_id) {
+                    dirty.push(job.col_id);
+                }
+                if job_rx.is_empty() {
+                    for id in dirty.drain(..) {
+                        if result_tx.send((id, columns[&id].summary.clone())).is_err() {
+                            return;
+                        }
+                    }
+                }
+            }
+        });
+        AggregateWorker { jobs, results }
+    }
+
+    pub fn extend(&self, col_id: usize, cells: Vec<Cell>) {
+        let _ = self.jobs.send(Job {
+            col_id,
+            cells,
+            restart: false,
+        });
+    }
+
+    // The column's summary from just these cells, e.g. once the shown rows change.
+    pub fn restart(&self, col_id: usize, cells: Vec<Cell>) {
+        let _ = self.jobs.send(Job {
+            col_id,
+            cells,
+            restart: true,
+        });
+    }
+
+    // never blocks, just drains whatever summaries are ready
+    pub fn poll(&self) -> Vec<(usize, Summary)> {
+        self.results.try_iter().collect()
+    }
+}
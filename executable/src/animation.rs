@@ -0,0 +1,204 @@
+use crate::{Clip, Vec2};
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+
+// Times are miniquad's date::now(), in seconds.
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Easing {
+    Linear,
+    CubicIn,
+    CubicOut,
+    CubicInOut,
+    EaseInOut, // sine, gentler than cubic
+    Spring,    // overshoots a little and settles
+}
+
+impl Easing {
+    // "linear", "cubic-in", "cubic-out", "cubic", "ease-in-out" or "spring"
+    pub fn parse(name: &str) -> Result<Easing, String> {
+        match name {
+            "" | "linear" => Ok(Easing::Linear),
+            "cubic-in" => Ok(Easing::CubicIn),
+            "cubic-out" => Ok(Easing::CubicOut),
+            "cubic" | "cubic-in-out" => Ok(Easing::CubicInOut),
+            "ease-in-out" => Ok(Easing::EaseInOut),
+            "spring" => Ok(Easing::Spring),
+            _ => Err(format!("unknown easing {:?}", name)),
+        }
+    }
+
+    // t from 0 to 1, how far along the value is. Not clamped, a spring goes past 1.
+    pub fn apply(self, t: f32) -> f32 {
+        match self {
+            Easing::Linear => t,
+            Easing::CubicIn => t * t * t,
+            Easing::CubicOut => 1.0 - (1.0 - t).powi(3),
+            Easing::CubicInOut => {
+                if t < 0.5 {
+                    4.0 * t * t * t
+                } else {
+                    1.0 - (2.0 - 2.0 * t).powi(3) / 2.0
+                }
+            }
+            Easing::EaseInOut => 0.5 - 0.5 * (PI * t).cos(),
+            Easing::Spring => {
+                // an underdamped spring's step response, close enough to 1 by t = 1 that
+                // snapping to the target at the end doesn't show
+                let decay = 6.0;
+                let freq = 2.5 * PI;
+                1.0 - (-decay * t).exp() * ((freq * t).cos() + decay / freq * (freq * t).sin())
+            }
+        }
+    }
+}
+
+pub trait Lerp: Copy {
+    fn lerp(self, to: Self, t: f32) -> Self;
+}
+
+impl Lerp for f32 {
+    fn lerp(self, to: f32, t: f32) -> f32 {
+        self + (to - self) * t
+    }
+}
+
+impl Lerp for Vec2 {
+    fn lerp(self, to: Vec2, t: f32) -> Vec2 {
+        Vec2 {
+            x: self.x.lerp(to.x, t),
+            y: self.y.lerp(to.y, t),
+        }
+    }
+}
+
+impl Lerp for Clip {
+    fn lerp(self, to: Clip, t: f32) -> Clip {
+        Clip {
+            pos: self.pos.lerp(to.pos, t),
+            // a spring can overshoot a small clip into a negative one
+            size: self.size.lerp(to.size, t).max_zero(),
+        }
+    }
+}
+
+// Colors, rgba from 0 to 1.
+impl Lerp for [f32; 4] {
+    fn lerp(self, to: [f32; 4], t: f32) -> [f32; 4] {
+        [0, 1, 2, 3].map(|i| self[i].lerp(to[i], t).clamp(0.0, 1.0))
+    }
+}
+
+// Adding or removing a clip can't be eased, it changes at the start.
+impl<T: Lerp> Lerp for Option<T> {
+    fn lerp(self, to: Option<T>, t: f32) -> Option<T> {
+        match (self, to) {
+            (Some(from), Some(to)) => Some(from.lerp(to, t)),
+            _ => to,
+        }
+    }
+}
+
+impl Vec2 {
+    fn max_zero(self) -> Vec2 {
+        Vec2 {
+            x: self.x.max(0.0),
+            y: self.y.max(0.0),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Segment<T> {
+    to: T,
+    duration: f32,
+    easing: Easing,
+}
+
+// A value and where it's headed: segments run one after another, each starting from where the
+// last one ended.
+#[derive(Debug, Clone)]
+pub struct Animated<T> {
+    target: T, // where the last segment ends, or the value when it's still
+    from: T,
+    start: f64,
+    segments: Vec<Segment<T>>,
+}
+
+impl<T: Lerp> Animated<T> {
+    pub fn new(value: T) -> Animated<T> {
+        Animated {
+            target: value,
+            from: value,
+            start: 0.0,
+            segments: Vec::new(),
+        }
+    }
+
+    // What it'll be once it stops moving. Layout works from this, only drawing uses at.
+    pub fn target(&self) -> T {
+        self.target
+    }
+
+    pub fn at(&self, time: f64) -> T {
+        let mut from = self.from;
+        let mut start = self.start;
+        for segment in &self.segments {
+            let end = start + segment.duration as f64;
+            if time < end {
+                if time <= start {
+                    return from;
+                }
+                let t = ((time - start) / segment.duration as f64) as f32;
+                return from.lerp(segment.to, segment.easing.apply(t));
+            }
+            from = segment.to;
+            start = end;
+        }
+        self.target
+    }
+
+    pub fn is_moving(&self, time: f64) -> bool {
+        let end = self.start + self.segments.iter().map(|s| s.duration as f64).sum::<f64>();
+        !self.segments.is_empty() && time < end
+    }
+
+    // Jumps there, dropping any animation.
+    pub fn set(&mut self, value: T) {
+        self.target = value;
+        self.from = value;
+        self.segments.clear();
+    }
+
+    // Interrupts whatever it was doing and heads for `to` from wherever it is now.
+    pub fn animate(&mut self, now: f64, to: T, duration: f32, easing: Easing) {
+        self.from = self.at(now);
+        self.start = now;
+        self.segments.clear();
+        self.push(now, to, duration, easing);
+    }
+
+    // Heads for `to` once the segments already queued are done.
+    pub fn then(&mut self, now: f64, to: T, duration: f32, easing: Easing) {
+        if !self.is_moving(now) {
+            self.from = self.target;
+            self.start = now;
+            self.segments.clear();
+        }
+        self.push(now, to, duration, easing);
+    }
+
+    fn push(&mut self, now: f64, to: T, duration: f32, easing: Easing) {
+        if duration <= 0.0 && self.segments.is_empty() {
+            self.from = to;
+            self.start = now;
+        } else {
+            self.segments.push(Segment {
+                to,
+                duration: duration.max(0.0),
+                easing,
+            });
+        }
+        self.target = to;
+    }
+}
@@ -0,0 +1,167 @@
+use crate::grid::{display_text, iso_timestamp, Align, Cell, GridColumn};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Quoting {
+    Minimal, // only fields containing the delimiter, quotes or newlines
+    Always,
+    NonNumeric,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ExportFormat {
+    Csv { delimiter: char, quoting: Quoting },
+    JsonLines,
+    Markdown,
+}
+
+impl ExportFormat {
+    // "csv", "csv delimiter=; quote=always", "jsonl" or "markdown"
+    pub fn parse(spec: &str) -> Result<ExportFormat, String> {
+        let mut words = spec.split_whitespace();
+        match words.next() {
+            Some("csv") => {
+                let mut delimiter = ',';
+                let mut quoting = Quoting::Minimal;
+                for rule in words {
+                    match rule.split_once('=') {
+                        Some(("delimiter", "tab")) => delimiter = '\t',
+                        Some(("delimiter", d)) if d.chars().count() == 1 => {
+                            delimiter = d.chars().next().unwrap()
+                        }
+                        Some(("quote", "minimal")) => quoting = Quoting::Minimal,
+                        Some(("quote", "always")) => quoting = Quoting::Always,
+                        Some(("quote", "nonnumeric")) => quoting = Quoting::NonNumeric,
+                        _ => return Err(format!("unknown csv rule {:?}", rule)),
+                    }
+                }
+                Ok(ExportFormat::Csv { delimiter, quoting })
+            }
+            Some("jsonl") if words.next().is_none() => Ok(ExportFormat::JsonLines),
+            Some("markdown") if words.next().is_none() => Ok(ExportFormat::Markdown),
+            _ => Err(format!("unknown export format {:?}", spec)),
+        }
+    }
+}
+
+// Writes rows straight from the columns' cells as it goes, nothing is collected up front. rows
+// are indices into the cells in the order they're written, see GridRows::exported.
+// Returns the number of data rows written.
+pub fn export_grid(
+    path: &str,
+    format: &ExportFormat,
+    columns: &[&GridColumn],
+    rows: &[usize],
+) -> io::Result<usize> {
+    let mut out = BufWriter::new(File::create(path)?);
+
+    match format {
+        ExportFormat::Csv { delimiter, quoting } => {
+            let header = columns
+                .iter()
+                .map(|c| csv_field(&c.name, false, *delimiter, *quoting))
+                .collect::<Vec<_>>();
+            writeln!(out, "{}", header.join(&delimiter.to_string()))?;
+            for &row in rows {
+                let fields = columns
+                    .iter()
+                    .map(|c| {
+                        let value = cell_at(c, row);
+                        let numeric = matches!(value, Cell::Int(_) | Cell::Float(_) | Cell::Null);
+                        csv_field(&raw_text(value), numeric, *delimiter, *quoting)
+                    })
+                    .collect::<Vec<_>>();
+                writeln!(out, "{}", fields.join(&delimiter.to_string()))?;
+            }
+        }
+        ExportFormat::JsonLines => {
+            for &row in rows {
+                let fields = columns
+                    .iter()
+                    .map(|c| format!("{}:{}", json_string(&c.name), json_value(cell_at(c, row))))
+                    .collect::<Vec<_>>();
+                writeln!(out, "{{{}}}", fields.join(","))?;
+            }
+        }
+        ExportFormat::Markdown => {
+            let header = columns
+                .iter()
+                .map(|c| markdown_cell(&c.name))
+                .collect::<Vec<_>>();
+            writeln!(out, "| {} |", header.join(" | "))?;
+            let rule = columns
+                .iter()
+                .map(|c| {
+                    let first = c.cells.iter().find(|v| **v != Cell::Null);
+                    match first.map_or(Align::Left, |v| c.format.align_for(v)) {
+                        Align::Left => ":---",
+                        Align::Right => "---:",
+                        Align::Center => ":---:",
+                    }
+                })
+                .collect::<Vec<_>>();
+            writeln!(out, "| {} |", rule.join(" | "))?;
+            for &row in rows {
+                let fields = columns
+                    .iter()
+                    .map(|c| markdown_cell(&display_text(cell_at(c, row), &c.format)))
+                    .collect::<Vec<_>>();
+                writeln!(out, "| {} |", fields.join(" | "))?;
+            }
+        }
+    }
+    out.flush()?;
+    Ok(rows.len())
+}
+
+fn cell_at(column: &GridColumn, row: usize) -> &Cell {
+    column.cells.get(row).unwrap_or(&Cell::Null)
+}
+
+// Machine readable text: full precision, ISO timestamps, all the bytes.
+fn raw_text(cell: &Cell) -> String {
+    match cell {
+        Cell::Null => String::new(),
+        Cell::Int(i) => i.to_string(),
+        Cell::Float(f) => f.to_string(),
+        Cell::Text(s) => s.clone(),
+        Cell::Timestamp(t) => iso_timestamp(*t),
+        Cell::Bytes(bytes) => bytes.iter().map(|b| format!("{:02x}", b)).collect(),
+    }
+}
+
+fn csv_field(text: &str, numeric: bool, delimiter: char, quoting: Quoting) -> String {
+    let needs_quotes = match quoting {
+        Quoting::Always => true,
+        Quoting::NonNumeric => !numeric,
+        Quoting::Minimal => text.contains([delimiter, '"', '\n', '\r']),
+    };
+    if needs_quotes {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_owned()
+    }
+}
+
+fn json_string(text: &str) -> String {
+    serde_json::to_string(text).expect("a str always serializes")
+}
+
+fn json_value(cell: &Cell) -> String {
+    match cell {
+        Cell::Null => String::from("null"),
+        Cell::Int(i) => i.to_string(),
+        Cell::Float(f) if f.is_finite() => f.to_string(),
+        Cell::Float(_) => String::from("null"),
+        c => json_string(&raw_text(c)),
+    }
+}
+
+fn markdown_cell(text: &str) -> String {
+    // a row is a line, CRLF counts as one break
+    text.replace('|', "\\|")
+        .replace("\r\n", " ")
+        .replace(['\n', '\r'], " ")
+}
@@ -0,0 +1,177 @@
+use crate::{layout, line_quads, pack_atlas, TextComponent, ATLAS_WIDTH};
+
+// Lines whose glyphs have to touch, at scales that put them on different subpixel bins.
+const JOINED: &[&str] = &["________", "────────", "━━━━━━━━", "════════"];
+const DPI_SCALES: &[f32] = &[1.0, 1.25, 1.5, 2.0];
+// Coverage below this along a stroke is a visible gap.
+const SOLID: f32 = 0.5;
+
+// A line as the pipeline would draw it, coverage from 0 to 1 for each physical pixel the line's
+// quads touch, row by row.
+pub struct Coverage {
+    pub width: usize,
+    pub data: Vec<f32>,
+}
+
+// Composites each line's glyphs on the CPU where TextLine puts their quads, one texel per
+// pixel and blended the way the pipeline blends, then walks the row with the most ink looking
+// for gaps. The errors say which line, scale and pixel.
+pub fn check_joins(text_component: &mut TextComponent) -> Result<usize, Vec<String>> {
+    let mut errors = Vec::new();
+    let mut checked = 0;
+    for &dpi_scale in DPI_SCALES {
+        text_component.dpi_scale = dpi_scale;
+        for &text in JOINED {
+            checked += 1;
+            if let Err(e) = render_line(text_component, text).and_then(|c| check_line(&c)) {
+                errors.push(format!("{:?} at {}x: {}", text, dpi_scale, e));
+            }
+        }
+    }
+    if errors.is_empty() {
+        Ok(checked)
+    } else {
+        Err(errors)
+    }
+}
+
+// Through the same atlas and quads the window draws with: pack_atlas rasterizes the line's
+// glyphs, line_quads places them, and each pixel takes the texel its center lands on.
+pub fn render_line(text_component: &mut TextComponent, text: &str) -> Result<Coverage, String> {
+    let line = layout(text, text_component);
+    text_component.glyph_loc.clear();
+    let atlas = pack_atlas(text_component, std::slice::from_ref(&line));
+    let atlas_w = ATLAS_WIDTH as usize;
+    let atlas_h = atlas.len() / 4 / atlas_w;
+    let (vertices, _) = line_quads(&line, text_component, atlas_w as f32, atlas_h as f32);
+    let dpi = text_component.dpi_scale;
+    // corners 0 and 2 of each quad, back in physical pixels, and their uvs
+    let quads: Vec<_> = vertices
+        .chunks(4)
+        .map(|q| {
+            let px = |v: f32| (v * dpi).round() as i32;
+            (
+                px(q[0].pos.x),
+                px(q[0].pos.y),
+                px(q[2].pos.x),
+                px(q[2].pos.y),
+                q[0].uv,
+                q[2].uv,
+            )
+        })
+        .collect();
+    let left = quads.iter().map(|q| q.0).min().ok_or("nothing drawn")?;
+    let top = quads.iter().map(|q| q.1).min().unwrap_or(0);
+    let right = quads.iter().map(|q| q.2).max().unwrap_or(left);
+    let bottom = quads.iter().map(|q| q.3).max().unwrap_or(top);
+    let (width, height) = ((right - left) as usize, (bottom - top) as usize);
+    let mut data = vec![0.0f32; width * height];
+    for &(x0, y0, x1, y1, uv0, uv1) in &quads {
+        for y in y0..y1 {
+            for x in x0..x1 {
+                // the uv at the pixel's center
+                let u = uv0.x + ((x - x0) as f32 + 0.5) / (x1 - x0) as f32 * (uv1.x - uv0.x);
+                let v = uv0.y + ((y - y0) as f32 + 0.5) / (y1 - y0) as f32 * (uv1.y - uv0.y);
+                let tx = ((u * atlas_w as f32) as usize).min(atlas_w - 1);
+                let ty = ((v * atlas_h as f32) as usize).min(atlas_h - 1);
+                let a = atlas[(ty * atlas_w + tx) * 4 + 3] as f32 / 255.0;
+                let c = &mut data[(y - top) as usize * width + (x - left) as usize];
+                // source over, like the pipeline's alpha blend
+                *c = a + *c * (1.0 - a);
+            }
+        }
+    }
+    Ok(Coverage { width, data })
+}
+
+fn check_line(coverage: &Coverage) -> Result<(), String> {
+    let width = coverage.width;
+    let stroke = coverage
+        .data
+        .chunks(width.max(1))
+        .max_by(|a, b| a.iter().sum::<f32>().total_cmp(&b.iter().sum::<f32>()))
+        .ok_or("nothing drawn")?;
+    let first = stroke
+        .iter()
+        .position(|&c| c >= SOLID)
+        .ok_or("no solid stroke")?;
+    let last = stroke.iter().rposition(|&c| c >= SOLID).unwrap_or(first);
+    match (first..=last).find(|&x| stroke[x] < SOLID) {
+        Some(x) => Err(format!(
+            "gap at pixel {} of {}, coverage {:.2}",
+            x, width, stroke[x]
+        )),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmic_text::{fontdb, FontSystem};
+    use std::path::PathBuf;
+
+    // Rendered and checked in under tests/golden, one per scale.
+    const GOLDEN: &[(&str, &str)] = &[("underscores", "____"), ("box-light", "────")];
+
+    // As a binary greymap, white where the line is drawn.
+    fn to_pgm(coverage: &Coverage) -> Vec<u8> {
+        let mut pgm = format!(
+            "P5\n{} {}\n255\n",
+            coverage.width,
+            coverage.data.len() / coverage.width.max(1)
+        )
+        .into_bytes();
+        pgm.extend(
+            coverage
+                .data
+                .iter()
+                .map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8),
+        );
+        pgm
+    }
+
+    fn tests_dir() -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests")
+    }
+
+    // Only the bundled font, so nothing depends on what's installed.
+    fn text_component() -> TextComponent {
+        let mut db = fontdb::Database::new();
+        db.load_font_file(tests_dir().join("fonts/DejaVuSansMono.ttf"))
+            .expect("the bundled font");
+        TextComponent::with_fonts(FontSystem::new_with_locale_and_db(
+            String::from("en-US"),
+            db,
+        ))
+    }
+
+    #[test]
+    fn joined_lines_have_no_gaps() {
+        if let Err(errors) = check_joins(&mut text_component()) {
+            panic!("{}", errors.join("\n"));
+        }
+    }
+
+    // UPDATE_GOLDEN=1 writes them instead, look at the images before committing them.
+    #[test]
+    fn lines_match_goldens() {
+        let mut text_component = text_component();
+        let mut differ = Vec::new();
+        for &(name, text) in GOLDEN {
+            for &dpi_scale in DPI_SCALES {
+                text_component.dpi_scale = dpi_scale;
+                let pgm = to_pgm(&render_line(&mut text_component, text).unwrap());
+                let path = tests_dir()
+                    .join("golden")
+                    .join(format!("{}-{}x.pgm", name, dpi_scale));
+                if std::env::var_os("UPDATE_GOLDEN").is_some() {
+                    std::fs::write(&path, &pgm).unwrap();
+                } else if std::fs::read(&path).ok() != Some(pgm) {
+                    differ.push(path.display().to_string());
+                }
+            }
+        }
+        assert!(differ.is_empty(), "not as golden: {}", differ.join(", "));
+    }
+}
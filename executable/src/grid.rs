@@ -0,0 +1,420 @@
+use crate::aggregate::{AggregateKind, Summary};
+use crate::animation::Animated;
+use crate::{layout, line_width, TextComponent, Vec2};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Typed values for a grid column. Lean pushes them as a (kind, raw) string pair, see Cell::parse.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Cell {
+    Null,
+    Int(i64),
+    Float(f64),
+    Text(String),
+    Timestamp(i64), // seconds since the unix epoch, UTC
+    Bytes(Vec<u8>),
+}
+
+impl Cell {
+    pub fn parse(kind: &str, raw: &str) -> Result<Cell, String> {
+        match kind {
+            "null" => Ok(Cell::Null),
+            "int" => raw
+                .trim()
+                .parse()
+                .map(Cell::Int)
+                .map_err(|e| format!("bad int cell {:?}: {}", raw, e)),
+            "float" => raw
+                .trim()
+                .parse()
+                .map(Cell::Float)
+                .map_err(|e| format!("bad float cell {:?}: {}", raw, e)),
+            "text" => Ok(Cell::Text(raw.to_owned())),
+            "timestamp" => raw
+                .trim()
+                .parse()
+                .map(Cell::Timestamp)
+                .map_err(|e| format!("bad timestamp cell {:?}: {}", raw, e)),
+            "bytes" => parse_hex(raw).map(Cell::Bytes),
+            _ => Err(format!("unknown cell kind {:?}", kind)),
+        }
+    }
+}
+
+fn parse_hex(raw: &str) -> Result<Vec<u8>, String> {
+    let digits: Vec<char> = raw
+        .trim()
+        .trim_start_matches("0x")
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect();
+    if digits.len() % 2 != 0 {
+        return Err(format!("odd number of hex digits in {:?}", raw));
+    }
+    digits
+        .chunks(2)
+        .map(|pair| {
+            let s: String = pair.iter().collect();
+            u8::from_str_radix(&s, 16).map_err(|e| format!("bad hex byte {:?}: {}", s, e))
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Align {
+    Left,
+    Right,
+    Center,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum TimestampStyle {
+    Iso,
+    Relative,
+}
+
+// Per-column formatting rules. Every type reads only the fields that concern it, so one column can
+// hold mixed kinds (e.g. ints and NULLs) and still render consistently.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CellFormat {
+    pub align: Option<Align>, // None picks the per-type default
+    pub decimals: usize,
+    pub thousands: bool,
+    pub timestamp: TimestampStyle,
+    pub max_width: Option<f32>, // in logical pixels, truncates with an ellipsis
+    pub bytes_preview: usize,
+    pub null_text: String,
+}
+
+impl Default for CellFormat {
+    fn default() -> CellFormat {
+        CellFormat {
+            align: None,
+            decimals: 2,
+            thousands: true,
+            timestamp: TimestampStyle::Iso,
+            max_width: None,
+            bytes_preview: 8,
+            null_text: String::from("∅ NULL"),
+        }
+    }
+}
+
+impl CellFormat {
+    // Space separated rules, e.g. "align=right decimals=0 no-thousands width=400 null=-".
+    pub fn parse(spec: &str) -> Result<CellFormat, String> {
+        let mut format = CellFormat::default();
+        for rule in spec.split_whitespace() {
+            let (key, value) = match rule.split_once('=') {
+                Some((k, v)) => (k, Some(v)),
+                None => (rule, None),
+            };
+            match (key, value) {
+                ("align", Some("left")) => format.align = Some(Align::Left),
+                ("align", Some("right")) => format.align = Some(Align::Right),
+                ("align", Some("center")) => format.align = Some(Align::Center),
+                ("decimals", Some(v)) => {
+                    format.decimals = v.parse().map_err(|e| format!("{}: {}", rule, e))?
+                }
+                ("thousands", None) => format.thousands = true,
+                ("no-thousands", None) => format.thousands = false,
+                ("iso", None) => format.timestamp = TimestampStyle::Iso,
+                ("relative", None) => format.timestamp = TimestampStyle::Relative,
+                ("width", Some(v)) => {
+                    format.max_width = Some(v.parse().map_err(|e| format!("{}: {}", rule, e))?)
+                }
+                ("bytes", Some(v)) => {
+                    format.bytes_preview = v.parse().map_err(|e| format!("{}: {}", rule, e))?
+                }
+                ("null", Some(v)) => format.null_text = v.to_owned(),
+                _ => return Err(format!("unknown format rule {:?}", rule)),
+            }
+        }
+        Ok(format)
+    }
+
+    pub fn align_for(&self, cell: &Cell) -> Align {
+        self.align.unwrap_or(match cell {
+            Cell::Int(_) | Cell::Float(_) => Align::Right,
+            Cell::Null => Align::Center,
+            _ => Align::Left,
+        })
+    }
+}
+
+pub struct GridColumn {
+    pub name: String,
+    pub cells: Vec<Cell>,
+    pub format: CellFormat,
+    pub footer: Vec<AggregateKind>, // one line each after the cells, none hides the footer
+    pub summary: Option<Summary>,   // of the shown rows, may lag behind cells
+}
+
+impl GridColumn {
+    pub fn new(name: String) -> GridColumn {
+        GridColumn {
+            name,
+            cells: Vec::new(),
+            format: CellFormat::default(),
+            footer: Vec::new(),
+            summary: None,
+        }
+    }
+
+    pub fn footer_lines(&self) -> Vec<String> {
+        let summary = match &self.summary {
+            Some(summary) => summary,
+            None => return self.footer.iter().map(|_| String::from("…")).collect(),
+        };
+        let show = |cell: Option<Cell>| match cell {
+            Some(cell) => display_text(&cell, &self.format),
+            None => String::from("-"),
+        };
+        self.footer
+            .iter()
+            // a sum or average of text, timestamps or bytes means nothing
+            .filter(|kind| {
+                summary.numeric > 0 || !matches!(kind, AggregateKind::Sum | AggregateKind::Avg)
+            })
+            .map(|kind| match kind {
+                AggregateKind::Count => format!("count {}", summary.count),
+                AggregateKind::Sum => format!("sum {}", show(Some(Cell::Float(summary.sum)))),
+                AggregateKind::Min => format!("min {}", show(summary.min.clone())),
+                AggregateKind::Max => format!("max {}", show(summary.max.clone())),
+                AggregateKind::Avg => format!("avg {}", show(summary.avg().map(Cell::Float))),
+                AggregateKind::Distinct => format!("distinct {}", summary.distinct),
+            })
+            .collect()
+    }
+
+    // The first footer line, after the header and the cells.
+    pub fn footer_start(&self) -> usize {
+        1 + self.cells.len()
+    }
+
+    // The header is line 0, cells follow, then the footer.
+    pub fn lines(&self, text_component: &mut TextComponent) -> Vec<String> {
+        let header = match self.format.max_width {
+            Some(max_width) => truncate_to_width(&self.name, max_width, text_component),
+            None => self.name.clone(),
+        };
+        std::iter::once(header)
+            .chain(
+                self.cells
+                    .iter()
+                    .map(|cell| format_cell(cell, &self.format, text_component)),
+            )
+            .chain(self.footer_lines())
+            .collect()
+    }
+
+    pub fn line_align(&self, line: usize) -> Align {
+        let cell = if line == 0 || line >= self.footer_start() {
+            // headers and footers line up with the column's values
+            self.cells.iter().find(|c| **c != Cell::Null)
+        } else {
+            self.cells.get(line - 1)
+        };
+        cell.map_or(self.format.align.unwrap_or(Align::Left), |c| {
+            self.format.align_for(c)
+        })
+    }
+}
+
+// The one scrollable grid. Grid columns keep their x from Lean, but inside a view their rows
+// start at the view's top edge. The first frozen_rows lines of each grid column (the header
+// is line 0) only scroll sideways, the first pinned_columns grid columns only scroll up and down.
+#[derive(Debug, Clone)]
+pub struct GridView {
+    pub pos: Vec2,
+    pub size: Vec2,
+    pub frozen_rows: usize,
+    pub pinned_columns: usize,
+    pub scroll: Animated<Vec2>,
+}
+
+// The grid's rows as Lean sorted, filtered and selected them, as indices into every grid
+// column's cells. Rust never reorders rows itself, it draws, sums up and exports what's here.
+#[derive(Debug, Clone, Default)]
+pub struct GridRows {
+    pub shown: Option<Vec<usize>>, // in order, None shows every row as pushed
+    pub selected: BTreeSet<usize>,
+    pub selected_columns: BTreeSet<usize>,
+}
+
+impl GridRows {
+    // The rows shown out of row_count, in order.
+    pub fn shown(&self, row_count: usize) -> Vec<usize> {
+        match &self.shown {
+            Some(rows) => rows
+                .iter()
+                .copied()
+                .filter(|&row| row < row_count)
+                .collect(),
+            None => (0..row_count).collect(),
+        }
+    }
+
+    // The selected rows in the order shown, or all the shown ones when none are selected.
+    pub fn exported(&self, row_count: usize) -> Vec<usize> {
+        let shown = self.shown(row_count);
+        if self.selected.is_empty() {
+            return shown;
+        }
+        shown
+            .into_iter()
+            .filter(|row| self.selected.contains(row))
+            .collect()
+    }
+
+    pub fn is_shown(&self, row: usize) -> bool {
+        self.shown.as_ref().map_or(true, |rows| rows.contains(&row))
+    }
+
+    // with none selected they all are
+    pub fn column_selected(&self, col_id: usize) -> bool {
+        self.selected_columns.is_empty() || self.selected_columns.contains(&col_id)
+    }
+}
+
+pub fn format_cell(cell: &Cell, format: &CellFormat, text_component: &mut TextComponent) -> String {
+    let text = display_text(cell, format);
+    match format.max_width {
+        Some(max_width) => truncate_to_width(&text, max_width, text_component),
+        None => text,
+    }
+}
+
+// The untruncated rendering, also what exports use for human readable output.
+pub fn display_text(cell: &Cell, format: &CellFormat) -> String {
+    match cell {
+        Cell::Null => format.null_text.clone(),
+        Cell::Int(i) => group_thousands(&i.to_string(), format.thousands),
+        Cell::Float(f) if f.is_finite() => {
+            group_thousands(&format!("{:.*}", format.decimals, f), format.thousands)
+        }
+        Cell::Float(f) => f.to_string(),
+        Cell::Text(s) => s.clone(),
+        Cell::Timestamp(t) => match format.timestamp {
+            TimestampStyle::Iso => iso_timestamp(*t),
+            TimestampStyle::Relative => relative_timestamp(*t, unix_now()),
+        },
+        Cell::Bytes(bytes) => hex_preview(bytes, format.bytes_preview),
+    }
+}
+
+// Groups the integer digits of an already formatted number, leaving sign and fraction alone.
+fn group_thousands(number: &str, thousands: bool) -> String {
+    if !thousands {
+        return number.to_owned();
+    }
+    let (sign, unsigned) = match number.strip_prefix('-') {
+        Some(rest) => ("-", rest),
+        None => ("", number),
+    };
+    let (int_part, fraction) = match unsigned.split_once('.') {
+        Some((i, f)) => (i, Some(f)),
+        None => (unsigned, None),
+    };
+    let mut grouped = String::new();
+    for (i, digit) in int_part.chars().enumerate() {
+        if i > 0 && (int_part.len() - i) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    match fraction {
+        Some(f) => format!("{sign}{grouped}.{f}"),
+        None => format!("{sign}{grouped}"),
+    }
+}
+
+pub fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+// Howard Hinnant's days-to-civil, so we don't pull in a date crate just for display.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+pub fn iso_timestamp(t: i64) -> String {
+    let (year, month, day) = civil_from_days(t.div_euclid(86400));
+    let secs = t.rem_euclid(86400);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs / 3600,
+        (secs / 60) % 60,
+        secs % 60
+    )
+}
+
+fn relative_timestamp(t: i64, now: i64) -> String {
+    // saturating, a cell can hold any i64
+    let delta = now.saturating_sub(t);
+    let secs = delta.unsigned_abs();
+    let amount = if secs < 60 {
+        format!("{}s", secs)
+    } else if secs < 3600 {
+        format!("{}m", secs / 60)
+    } else if secs < 86400 {
+        format!("{}h", secs / 3600)
+    } else {
+        format!("{}d", secs / 86400)
+    };
+    if delta >= 0 {
+        format!("{} ago", amount)
+    } else {
+        format!("in {}", amount)
+    }
+}
+
+fn hex_preview(bytes: &[u8], preview: usize) -> String {
+    let shown: String = bytes
+        .iter()
+        .take(preview)
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    if bytes.len() > preview {
+        format!("0x{}… ({} bytes)", shown, bytes.len())
+    } else {
+        format!("0x{}", shown)
+    }
+}
+
+// Shapes with the same layout() the text is drawn with, so the cut lands on what is actually visible.
+pub fn truncate_to_width(text: &str, max_width: f32, text_component: &mut TextComponent) -> String {
+    if line_width(&layout(text, text_component), text_component.dpi_scale) <= max_width {
+        return text.to_owned();
+    }
+    let chars: Vec<char> = text.chars().collect();
+    // binary search the longest prefix that still fits along with the ellipsis
+    let mut lo = 0;
+    let mut hi = chars.len();
+    while lo < hi {
+        let mid = (lo + hi).div_ceil(2);
+        let candidate: String = chars[..mid].iter().chain(['…'].iter()).collect();
+        if line_width(&layout(candidate, text_component), text_component.dpi_scale) <= max_width {
+            lo = mid;
+        } else {
+            hi = mid - 1;
+        }
+    }
+    chars[..lo].iter().chain(['…'].iter()).collect()
+}
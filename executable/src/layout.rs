@@ -0,0 +1,688 @@
+use crate::lean_experiments::gui_api::ColID;
+use crate::{intersect_clip, Clip, Vec2};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+// A size along the parent's direction: fixed logical pixels, or a share of whatever the fixed
+// ones leave over.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Size {
+    Px(f32),
+    Fr(f32),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Item {
+    pub size: Size,
+    pub node: Node,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Node {
+    HSplit(Vec<Item>), // side by side, left to right
+    VSplit(Vec<Item>), // stacked, top to bottom
+    Stack(Vec<Node>),  // on top of each other, each gets the whole rect
+    Scroll {
+        x: f32, // how far the contents are moved left and up, they're clipped to the rect
+        y: f32,
+        child: Box<Node>,
+    },
+    Pad {
+        top: f32,
+        right: f32,
+        bottom: f32,
+        left: f32,
+        child: Box<Node>,
+    },
+    // Two panes with a divider between that can be dragged, ratio is the first pane's share of
+    // the room beside the divider and neither pane gets smaller than min while there's room.
+    // The name is what the drag is reported to Lean under.
+    Splitter {
+        name: String,
+        horizontal: bool, // side by side like hsplit
+        ratio: f32,
+        min: f32,
+        first: Box<Node>,
+        second: Box<Node>,
+    },
+    Column(ColID), // a text or grid column is placed in the rect
+    Grid,          // the grid view fills the rect
+    Empty,
+}
+
+// Where a column goes: its rect, moved by any scroll above it, and what of it shows.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Placement {
+    pub rect: Clip,
+    pub clip: Clip,
+}
+
+// Logical pixels across a splitter's divider.
+pub const DIVIDER: f32 = 6.0;
+// How far outside the divider a press still grabs it.
+const GRAB_SLOP: f32 = 3.0;
+
+// A splitter as it was laid out, for drawing its divider and dragging it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Divider {
+    pub name: String,
+    pub rect: Clip,
+    pub clip: Clip, // what of the divider shows
+    horizontal: bool,
+    ratio: f32, // as laid out, dragged or the tree's
+    start: f32, // where the first pane starts, along the splitter
+    room: f32,  // both panes together
+    min: f32,
+}
+
+impl Divider {
+    // The first pane's size at a ratio, with both kept at min if there's room for that.
+    fn first(&self, ratio: f32) -> f32 {
+        let first = self.room * ratio.clamp(0.0, 1.0);
+        if self.room >= 2.0 * self.min {
+            first.clamp(self.min, self.room - self.min)
+        } else {
+            first
+        }
+    }
+
+    // The ratio that puts the divider's middle at the mouse.
+    fn ratio_at(&self, mouse: Vec2) -> f32 {
+        if self.room <= 0.0 {
+            return 0.5;
+        }
+        let along = if self.horizontal { mouse.x } else { mouse.y };
+        self.first((along - self.start - DIVIDER / 2.0) / self.room) / self.room
+    }
+
+    fn grabs(&self, mouse: Vec2) -> bool {
+        let r = self.clip;
+        r.size.x > 0.0
+            && r.size.y > 0.0
+            && mouse.x >= r.pos.x - GRAB_SLOP
+            && mouse.x <= r.pos.x + r.size.x + GRAB_SLOP
+            && mouse.y >= r.pos.y - GRAB_SLOP
+            && mouse.y <= r.pos.y + r.size.y + GRAB_SLOP
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Placements {
+    pub columns: BTreeMap<ColID, Placement>,
+    pub grid: Option<Clip>,
+    pub dividers: Vec<Divider>,
+}
+
+impl Node {
+    // e.g. "hsplit(250 #3, 1fr vsplit(1fr pad(8, #4), 120 scroll(0 40, #5)))": #n is column n,
+    // grid the grid view and _ empty space. Every child of hsplit or vsplit has a size in front,
+    // pixels or fr, stack takes children without. pad takes 1, 2 or 4 numbers like CSS. cols
+    // and rows are the same as hsplit and vsplit. "hsplitter(\"tree\" 0.25 120, #3, #4)" and
+    // vsplitter are draggable, with a name, ratio and min size before their two panes. The name
+    // is a string with Lean's escapes, or a bare word. Only scroll's offset can be negative,
+    // sizes and padding below 0 count as 0.
+    pub fn parse(spec: &str) -> Result<Node, String> {
+        let mut parser = Parser {
+            tokens: tokenize(spec)?,
+            at: 0,
+        };
+        let node = parser.node()?;
+        match parser.next() {
+            None => Ok(node),
+            Some(token) => Err(format!("unexpected {:?} after the layout", token)),
+        }
+    }
+
+    // A column in the tree twice ends up where it was placed last. dragged are splitter ratios
+    // to use instead of the tree's.
+    pub fn compute(
+        &self,
+        rect: Clip,
+        clip: Clip,
+        dragged: &BTreeMap<String, f32>,
+        out: &mut Placements,
+    ) {
+        match self {
+            Node::HSplit(items) => {
+                let xs = split(rect.size.x, items);
+                for (item, (x, w)) in items.iter().zip(xs) {
+                    let child = Clip {
+                        pos: Vec2 {
+                            x: rect.pos.x + x,
+                            y: rect.pos.y,
+                        },
+                        size: Vec2 {
+                            x: w,
+                            y: rect.size.y,
+                        },
+                    };
+                    item.node
+                        .compute(child, intersect_clip(child, Some(clip)), dragged, out);
+                }
+            }
+            Node::VSplit(items) => {
+                let ys = split(rect.size.y, items);
+                for (item, (y, h)) in items.iter().zip(ys) {
+                    let child = Clip {
+                        pos: Vec2 {
+                            x: rect.pos.x,
+                            y: rect.pos.y + y,
+                        },
+                        size: Vec2 {
+                            x: rect.size.x,
+                            y: h,
+                        },
+                    };
+                    item.node
+                        .compute(child, intersect_clip(child, Some(clip)), dragged, out);
+                }
+            }
+            Node::Stack(layers) => {
+                for layer in layers {
+                    layer.compute(rect, clip, dragged, out);
+                }
+            }
+            Node::Scroll { x, y, child } => {
+                let moved = Clip {
+                    pos: Vec2 {
+                        x: rect.pos.x - x,
+                        y: rect.pos.y - y,
+                    },
+                    size: rect.size,
+                };
+                child.compute(moved, clip, dragged, out);
+            }
+            Node::Pad {
+                top,
+                right,
+                bottom,
+                left,
+                child,
+            } => {
+                let inner = Clip {
+                    pos: Vec2 {
+                        x: rect.pos.x + left,
+                        y: rect.pos.y + top,
+                    },
+                    size: Vec2 {
+                        x: (rect.size.x - left - right).max(0.0),
+                        y: (rect.size.y - top - bottom).max(0.0),
+                    },
+                };
+                child.compute(inner, intersect_clip(inner, Some(clip)), dragged, out);
+            }
+            Node::Splitter {
+                name,
+                horizontal,
+                ratio,
+                min,
+                first,
+                second,
+            } => {
+                let (start, along) = if *horizontal {
+                    (rect.pos.x, rect.size.x)
+                } else {
+                    (rect.pos.y, rect.size.y)
+                };
+                let mut divider = Divider {
+                    name: name.clone(),
+                    rect,
+                    clip,
+                    horizontal: *horizontal,
+                    ratio: *dragged.get(name).unwrap_or(ratio),
+                    start,
+                    room: (along - DIVIDER).max(0.0),
+                    min: *min,
+                };
+                let first_size = divider.first(divider.ratio);
+                // the first pane, the divider and the second along the splitter
+                let piece = |from: f32, size: f32| {
+                    if *horizontal {
+                        Clip {
+                            pos: Vec2 {
+                                x: start + from,
+                                y: rect.pos.y,
+                            },
+                            size: Vec2 {
+                                x: size,
+                                y: rect.size.y,
+                            },
+                        }
+                    } else {
+                        Clip {
+                            pos: Vec2 {
+                                x: rect.pos.x,
+                                y: start + from,
+                            },
+                            size: Vec2 {
+                                x: rect.size.x,
+                                y: size,
+                            },
+                        }
+                    }
+                };
+                let first_rect = piece(0.0, first_size);
+                let second_rect = piece(first_size + DIVIDER, (divider.room - first_size).max(0.0));
+                divider.rect = piece(first_size, DIVIDER.min(along));
+                divider.clip = intersect_clip(divider.rect, Some(clip));
+                first.compute(
+                    first_rect,
+                    intersect_clip(first_rect, Some(clip)),
+                    dragged,
+                    out,
+                );
+                second.compute(
+                    second_rect,
+                    intersect_clip(second_rect, Some(clip)),
+                    dragged,
+                    out,
+                );
+                out.dividers.push(divider);
+            }
+            Node::Column(col) => {
+                out.columns.insert(*col, Placement { rect, clip });
+            }
+            Node::Grid => out.grid = Some(clip),
+            Node::Empty => {}
+        }
+    }
+}
+
+// The tree Lean last described and where it put everything. Lean can describe it again on
+// every event, only what actually moved is handed back to be applied.
+#[derive(Debug, Default)]
+pub struct Layout {
+    root: Option<Node>,
+    window: Option<Clip>,
+    placed: Placements,
+    // Splitter ratios dragged to, until Lean sets a tree with them in. Lean is told on release,
+    // and keeping the ratio in its state is what makes it last.
+    dragged: BTreeMap<String, f32>,
+    dragging: Option<String>,
+}
+
+impl Layout {
+    // False when it's the same tree as before.
+    pub fn set_root(&mut self, root: Option<Node>) -> bool {
+        if self.root == root {
+            return false;
+        }
+        self.root = root;
+        // columns left out of the new tree stay where they are
+        self.window = None;
+        let dragging = &self.dragging;
+        self.dragged
+            .retain(|name, _| Some(name) == dragging.as_ref());
+        true
+    }
+
+    pub fn dividers(&self) -> &[Divider] {
+        &self.placed.dividers
+    }
+
+    // The name of the splitter being dragged.
+    pub fn dragging(&self) -> Option<&str> {
+        self.dragging.as_deref()
+    }
+
+    // True when the press is on a divider, which then follows the mouse until it's let go.
+    pub fn start_drag(&mut self, mouse: Vec2) -> bool {
+        // the last placed is on top
+        match self.placed.dividers.iter().rev().find(|d| d.grabs(mouse)) {
+            Some(divider) => {
+                self.dragging = Some(divider.name.clone());
+                true
+            }
+            None => false,
+        }
+    }
+
+    // True while dragging, reflow to see the divider move.
+    pub fn drag_to(&mut self, mouse: Vec2) -> bool {
+        let name = match &self.dragging {
+            Some(name) => name,
+            None => return false,
+        };
+        if let Some(divider) = self.placed.dividers.iter().find(|d| &d.name == name) {
+            self.dragged.insert(name.clone(), divider.ratio_at(mouse));
+        }
+        true
+    }
+
+    // The splitter let go and its ratio, None when nothing was being dragged.
+    pub fn end_drag(&mut self) -> Option<(String, f32)> {
+        let name = self.dragging.take()?;
+        let ratio = match self.placed.dividers.iter().find(|d| d.name == name) {
+            Some(divider) => divider.ratio,
+            None => *self.dragged.get(&name)?,
+        };
+        Some((name, ratio))
+    }
+
+    // The placements that changed since the last reflow, and the grid view's rect whenever
+    // there is one, Lean's setGridView may have moved it since. Only ids below columns exist so
+    // far, the tree's others are left unplaced until a reflow after they're made.
+    pub fn reflow(&mut self, window: Clip, columns: usize) -> Placements {
+        let root = match &self.root {
+            Some(root) => root,
+            None => return Placements::default(),
+        };
+        let mut placed = Placements::default();
+        root.compute(window, window, &self.dragged, &mut placed);
+        placed.columns.retain(|col, _| (*col as usize) < columns);
+        let changed = Placements {
+            columns: placed
+                .columns
+                .iter()
+                .filter(|(col, placement)| {
+                    self.window.is_none() || self.placed.columns.get(col) != Some(placement)
+                })
+                .map(|(col, placement)| (*col, *placement))
+                .collect(),
+            grid: placed.grid,
+            dividers: placed.dividers.clone(),
+        };
+        self.window = Some(window);
+        self.placed = placed;
+        changed
+    }
+}
+
+// Offsets and lengths along a total. Fixed sizes that don't fit are squeezed to it, fr ones
+// then get nothing.
+fn split(total: f32, items: &[Item]) -> Vec<(f32, f32)> {
+    let fixed: f32 = items
+        .iter()
+        .map(|item| match item.size {
+            Size::Px(px) => px,
+            Size::Fr(_) => 0.0,
+        })
+        .sum();
+    let frs: f32 = items
+        .iter()
+        .map(|item| match item.size {
+            Size::Px(_) => 0.0,
+            Size::Fr(fr) => fr,
+        })
+        .sum();
+    let squeeze = if fixed > total && fixed > 0.0 {
+        total / fixed
+    } else {
+        1.0
+    };
+    let left = (total - fixed).max(0.0);
+    let mut offset = 0.0;
+    items
+        .iter()
+        .map(|item| {
+            let length = match item.size {
+                Size::Px(px) => px * squeeze,
+                Size::Fr(fr) if frs > 0.0 => left * fr / frs,
+                Size::Fr(_) => 0.0,
+            };
+            let at = offset;
+            offset += length;
+            (at, length)
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String), // hsplit, pad, grid, _ ...
+    Str(String),  // "a splitter's name", unescaped
+    Number(f32),
+    Fr(f32),
+    Column(ColID),
+    Open,
+    Close,
+    Comma,
+}
+
+fn tokenize(spec: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = spec.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            ' ' | '\t' | '\n' => {
+                chars.next();
+            }
+            '(' | ')' | ',' => {
+                chars.next();
+                tokens.push(match c {
+                    '(' => Token::Open,
+                    ')' => Token::Close,
+                    _ => Token::Comma,
+                });
+            }
+            '#' => {
+                chars.next();
+                let digits = take_while(&mut chars, |c| c.is_ascii_digit());
+                let col = digits
+                    .parse()
+                    .map_err(|_| format!("expected a column id after #, got {:?}", digits))?;
+                tokens.push(Token::Column(col));
+            }
+            c if c.is_ascii_digit() || c == '.' || c == '-' => {
+                let sign = if c == '-' {
+                    chars.next();
+                    "-"
+                } else {
+                    ""
+                };
+                let number =
+                    sign.to_owned() + &take_while(&mut chars, |c| c.is_ascii_digit() || c == '.');
+                let value: f32 = number
+                    .parse()
+                    .map_err(|_| format!("bad number {:?}", number))?;
+                let unit = take_while(&mut chars, |c| c.is_ascii_alphabetic());
+                tokens.push(match unit.as_str() {
+                    "" | "px" => Token::Number(value),
+                    "fr" => Token::Fr(value),
+                    _ => return Err(format!("unknown unit {:?} on {}", unit, number)),
+                });
+            }
+            '"' => {
+                chars.next();
+                tokens.push(Token::Str(string(&mut chars)?));
+            }
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                tokens.push(Token::Word(take_while(&mut chars, |c| {
+                    c.is_ascii_alphanumeric() || c == '_'
+                })));
+            }
+            _ => return Err(format!("unexpected {:?} in layout", c)),
+        }
+    }
+    Ok(tokens)
+}
+
+fn take_while(
+    chars: &mut std::iter::Peekable<std::str::Chars>,
+    keep: impl Fn(char) -> bool,
+) -> String {
+    let mut taken = String::new();
+    while let Some(&c) = chars.peek() {
+        if !keep(c) {
+            break;
+        }
+        taken.push(c);
+        chars.next();
+    }
+    taken
+}
+
+// The rest of a string after its opening quote, with the escapes Lean's String.quote makes.
+fn string(chars: &mut std::iter::Peekable<std::str::Chars>) -> Result<String, String> {
+    let mut taken = String::new();
+    loop {
+        match chars.next() {
+            None => return Err(format!("unterminated string {:?}", taken)),
+            Some('"') => return Ok(taken),
+            Some('\\') => match chars.next() {
+                Some('n') => taken.push('\n'),
+                Some('t') => taken.push('\t'),
+                Some('r') => taken.push('\r'),
+                Some(c @ ('\\' | '"' | '\'')) => taken.push(c),
+                Some('x') => {
+                    let hex: String = chars.by_ref().take(2).collect();
+                    let c = u8::from_str_radix(&hex, 16)
+                        .map_err(|_| format!("bad escape \\x{} in string", hex))?;
+                    taken.push(c as char);
+                }
+                c => return Err(format!("unknown escape {:?} in string", c)),
+            },
+            Some(c) => taken.push(c),
+        }
+    }
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    at: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.at).cloned();
+        self.at += 1;
+        token
+    }
+
+    fn node(&mut self) -> Result<Node, String> {
+        match self.next() {
+            Some(Token::Column(col)) => Ok(Node::Column(col)),
+            Some(Token::Word(word)) => match word.as_str() {
+                "_" => Ok(Node::Empty),
+                "grid" => Ok(Node::Grid),
+                "hsplit" | "cols" => Ok(Node::HSplit(self.items()?)),
+                "vsplit" | "rows" => Ok(Node::VSplit(self.items()?)),
+                "hsplitter" | "vsplitter" => {
+                    self.expect(Token::Open, "( after a splitter")?;
+                    let name = match self.next() {
+                        Some(Token::Str(name)) | Some(Token::Word(name)) => name,
+                        token => return Err(format!("expected the splitter's name, got {:?}", token)),
+                    };
+                    let (ratio, min) = match self.numbers()?[..] {
+                        [ratio] => (ratio, 0.0),
+                        [ratio, min] => (ratio, min.max(0.0)),
+                        _ => return Err(String::from("a splitter takes a ratio and a min size")),
+                    };
+                    let first = Box::new(self.node()?);
+                    self.expect(Token::Comma, ", between the splitter's panes")?;
+                    let second = Box::new(self.node()?);
+                    self.expect(Token::Close, ") after the splitter's panes")?;
+                    Ok(Node::Splitter {
+                        name,
+                        horizontal: word == "hsplitter",
+                        ratio,
+                        min,
+                        first,
+                        second,
+                    })
+                }
+                "stack" => {
+                    self.expect(Token::Open, "( after stack")?;
+                    let mut layers = Vec::new();
+                    if self.peek() == Some(&Token::Close) {
+                        self.next();
+                        return Ok(Node::Stack(layers));
+                    }
+                    loop {
+                        layers.push(self.node()?);
+                        if self.list_ends()? {
+                            return Ok(Node::Stack(layers));
+                        }
+                    }
+                }
+                "scroll" => {
+                    self.expect(Token::Open, "( after scroll")?;
+                    let offset = self.numbers()?;
+                    let (x, y) = match offset[..] {
+                        [x, y] => (x, y),
+                        _ => return Err(String::from("scroll takes x and y before its child")),
+                    };
+                    let child = Box::new(self.node()?);
+                    self.expect(Token::Close, ") after scroll's child")?;
+                    Ok(Node::Scroll { x, y, child })
+                }
+                "pad" => {
+                    self.expect(Token::Open, "( after pad")?;
+                    let pads: Vec<f32> = self.numbers()?.iter().map(|n| n.max(0.0)).collect();
+                    let (top, right, bottom, left) = match pads[..] {
+                        [all] => (all, all, all, all),
+                        [vertical, horizontal] => (vertical, horizontal, vertical, horizontal),
+                        [top, right, bottom, left] => (top, right, bottom, left),
+                        _ => return Err(String::from("pad takes 1, 2 or 4 numbers")),
+                    };
+                    let child = Box::new(self.node()?);
+                    self.expect(Token::Close, ") after pad's child")?;
+                    Ok(Node::Pad {
+                        top,
+                        right,
+                        bottom,
+                        left,
+                        child,
+                    })
+                }
+                _ => Err(format!(
+                    "unknown layout {:?}, expected hsplit, vsplit, hsplitter, vsplitter, stack, scroll, pad, #n, grid or _",
+                    word
+                )),
+            },
+            Some(token) => Err(format!("expected a layout, got {:?}", token)),
+            None => Err(String::from("expected a layout, got the end")),
+        }
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.at)
+    }
+
+    fn expect(&mut self, token: Token, what: &str) -> Result<(), String> {
+        match self.next() {
+            Some(t) if t == token => Ok(()),
+            t => Err(format!("expected {}, got {:?}", what, t)),
+        }
+    }
+
+    // Plain numbers up to the comma, e.g. pad's and scroll's.
+    fn numbers(&mut self) -> Result<Vec<f32>, String> {
+        let mut numbers = Vec::new();
+        while let Some(Token::Number(n)) = self.peek() {
+            numbers.push(*n);
+            self.next();
+        }
+        self.expect(Token::Comma, ", between the numbers and the child")?;
+        Ok(numbers)
+    }
+
+    // After a list item, true at the closing paren.
+    fn list_ends(&mut self) -> Result<bool, String> {
+        match self.next() {
+            Some(Token::Comma) => Ok(false),
+            Some(Token::Close) => Ok(true),
+            token => Err(format!("expected , or ), got {:?}", token)),
+        }
+    }
+
+    fn items(&mut self) -> Result<Vec<Item>, String> {
+        self.expect(Token::Open, "( after hsplit or vsplit")?;
+        let mut items = Vec::new();
+        loop {
+            let size = match self.next() {
+                Some(Token::Number(px)) => Size::Px(px.max(0.0)),
+                Some(Token::Fr(fr)) => Size::Fr(fr.max(0.0)),
+                Some(Token::Close) if items.is_empty() => return Ok(items),
+                token => return Err(format!("expected a size like 200 or 1fr, got {:?}", token)),
+            };
+            items.push(Item {
+                size,
+                node: self.node()?,
+            });
+            if self.list_ends()? {
+                return Ok(items);
+            }
+        }
+    }
+}
@@ -1,10 +1,17 @@
+use lean_ref::IntoLean;
 use memoffset::raw_field;
-use std::{
-    collections::{BTreeMap, HashMap},
-    ffi, mem, ptr, slice, str,
-};
+use saved_state::SavedState;
+use std::path::{Path, PathBuf};
+use std::{ffi, mem, ptr, slice, str};
+use structural::Structural;
 
+pub mod audit;
+pub mod bindings;
 pub mod gui_api;
+pub mod lean_ref;
+pub mod saved_state;
+pub mod session;
+pub mod structural;
 
 #[repr(C)]
 pub struct LeanObject {
@@ -35,6 +42,14 @@ pub struct LeanBoxedU64 {
     m_obj: u64,
 }
 
+#[repr(C)]
+pub struct LeanArray {
+    m_header: LeanObject,
+    m_size: usize,
+    m_capacity: usize,
+    m_data: [*mut LeanObject; 0],
+}
+
 #[repr(C)]
 pub struct LeanOKCtor {
     m_header: LeanObject,
@@ -57,10 +72,9 @@ pub struct LeanOKU64Ctor {
 }
 
 #[repr(C)]
-pub struct LeanOpaqueCtor {
+pub struct LeanCtorObject {
     m_header: LeanObject,
-    m_objs_0: *mut LeanObject,
-    m_objs_1: libc::uintptr_t,
+    m_objs: [*mut LeanObject; 0],
 }
 
 #[repr(C)]
@@ -130,7 +144,8 @@ extern "C" {
     fn lean_initialize_thread();
     fn lean_finalize_thread();
     fn lean_io_mark_end_initialization();
-    fn lean_io_result_show_error(o: *mut LeanObject);
+    fn lean_io_error_to_string(err: *mut LeanObject) -> *mut LeanObject;
+    fn lean_mk_io_user_error(msg: *mut LeanObject) -> *mut LeanObject;
     fn lean_inc_ref_cold(o: *mut LeanObject);
     fn lean_dec_ref_cold(o: *mut LeanObject);
     pub fn lean_alloc_small(sz: u8, slot_idx: u8) -> *mut libc::c_void;
@@ -141,27 +156,6 @@ extern "C" {
     ) -> *mut LeanExternalClass;
 }
 
-// #[link(name = "Structural-1")]
-#[link(name = "Structural")]
-extern "C" {
-    fn initialize_Structural(builtin: u8, io: libc::uintptr_t) -> *mut LeanObject;
-    fn leans_answer(unit: libc::uintptr_t) -> u8;
-    fn leans_other_answer(_: u8) -> u8;
-    fn lean_use_callback(a: *mut LeanClosure) -> u8;
-    fn lean_use_io_callback(a: *mut LeanIOClosure) -> *mut LeanObject;
-    fn lean_use_io_string_callback(
-        a: *mut LeanIOStringClosure,
-        io: libc::uintptr_t,
-    ) -> *mut LeanObject;
-    fn lean_use_on_event(
-        idk: libc::uintptr_t,
-        oe: *mut Closure<gui_api::EventCallback>,
-        ce: *mut Closure<gui_api::EventCallback>,
-        io: libc::uintptr_t,
-    ) -> *mut LeanOKCtor;
-    fn lean_on_init(io: libc::uintptr_t) -> *mut LeanOpaqueCtor;
-}
-
 fn lean_dec_ref(o: *mut LeanObject) {
     unsafe {
         if (*o).m_rc > 1 {
@@ -288,27 +282,6 @@ fn lean_io_result_mk_string_ok(string: &str) -> *mut LeanOKStringCtor {
     }
 }
 
-fn lean_io_result_mk_u64_ok(val: u64) -> *mut LeanOKU64Ctor {
-    unsafe {
-        let bv = lean_alloc_small(16, (16 / 8) - 1) as *mut LeanBoxedU64;
-        // I guess we set this to 2 because it's nested? We segfault otherwise.
-        (*bv).m_header.m_rc = 2;
-        (*bv).m_header.m_tag = 0;
-        (*bv).m_header.m_other = 1;
-        (*bv).m_header.m_cs_sz = 0;
-        (*bv).m_obj = val;
-
-        let m = lean_alloc_small(24, (24 / 8) - 1) as *mut LeanOKU64Ctor;
-        (*m).m_header.m_rc = 1;
-        (*m).m_header.m_tag = 0;
-        (*m).m_header.m_other = 2;
-        (*m).m_header.m_cs_sz = 0;
-        (*m).m_objs_0 = bv;
-        (*m).m_objs_1 = LEAN_UNIT;
-        m
-    }
-}
-
 // copies the string to Lean's memory.
 fn mk_lean_string(string: &str) -> *mut LeanString {
     let cstring = ffi::CString::new(string.to_string()).unwrap();
@@ -344,6 +317,33 @@ fn mk_external_object(
     }
 }
 
+// Constructor with only boxed fields, e.g. a structure of closures. Takes ownership of objs.
+pub fn mk_ctor(tag: u8, objs: &[*mut LeanObject]) -> *mut LeanObject {
+    mk_ctor_with_scalars(tag, objs, &[])
+}
+
+// Scalar fields are stored after the objects, already in Lean's order (see lean_ref::Scalar).
+pub fn mk_ctor_with_scalars(tag: u8, objs: &[*mut LeanObject], scalars: &[u8]) -> *mut LeanObject {
+    let unpadded = mem::size_of::<LeanObject>() + mem::size_of_val(objs) + scalars.len();
+    // lean_alloc_ctor_memory rounds up to a whole word and zeroes the padding
+    let sz = unpadded.div_ceil(8) * 8;
+    unsafe {
+        let m = lean_alloc_object(sz) as *mut LeanCtorObject;
+        (*m).m_header.m_rc = 1;
+        (*m).m_header.m_tag = tag;
+        (*m).m_header.m_other = objs.len() as u8; // number of object fields
+        (*m).m_header.m_cs_sz = sz as u16;
+        let fields = raw_field!(m, LeanCtorObject, m_objs) as *mut *mut LeanObject;
+        for (i, obj) in objs.iter().enumerate() {
+            *fields.add(i) = *obj;
+        }
+        let scalar_area = fields.add(objs.len()) as *mut u8;
+        ptr::write_bytes(scalar_area, 0, sz - (unpadded - scalars.len()));
+        ptr::copy_nonoverlapping(scalars.as_ptr(), scalar_area, scalars.len());
+        m as *mut LeanObject
+    }
+}
+
 // Arity includes the closed over object, which must be the first arg to the callback
 fn mk_closure_2<T>(
     callback: T,
@@ -369,7 +369,53 @@ pub extern "C" fn rusts_answer() -> *mut LeanOKCtor {
     lean_io_result_mk_ok(90)
 }
 
-pub fn test_lean() -> gui_api::Interpreter {
+// The FFI experiments from before the Gui bindings, they only print.
+unsafe fn run_experiments(
+    lib: &Structural,
+    interp: &mut gui_api::Interpreter,
+) -> Result<(), String> {
+    let leans_answer: unsafe extern "C" fn(libc::uintptr_t) -> u8 = lib.get("leans_answer")?;
+    let lean_use_callback: unsafe extern "C" fn(*mut LeanClosure) -> u8 =
+        lib.get("lean_use_callback")?;
+    let lean_use_io_callback: unsafe extern "C" fn(*mut LeanIOClosure) -> *mut LeanObject =
+        lib.get("lean_use_io_callback")?;
+    let lean_use_io_string_callback: unsafe extern "C" fn(
+        *mut LeanIOStringClosure,
+        libc::uintptr_t,
+    ) -> *mut LeanObject = lib.get("lean_use_io_string_callback")?;
+
+    let a = leans_answer(LEAN_UNIT);
+    println!("Lean's answer: {}", a);
+    // let b = leans_other_answer(12);
+    // println!("Lean's other answer: {}", b);
+    let cb = mk_closure();
+    let r = lean_use_callback(cb);
+    println!("Lean's callback: {}", r);
+
+    let cbio = mk_io_closure();
+    match lean_ref::take_io_result(lean_use_io_callback(cbio)) {
+        Ok(r2) => println!("Lean's io callback: {}", r2.as_ptr() as usize >> 1),
+        Err(e) => interp.report_error("leanUseIOCallback", e),
+    }
+
+    let cbios = mk_io_string_closure();
+    match lean_ref::take_io_result(lean_use_io_string_callback(cbios, LEAN_UNIT))
+        .and_then(|r3| r3.downcast::<String>())
+    {
+        Ok(r3) => println!("Lean's io string: {}", r3.as_str()),
+        Err(e) => interp.report_error("leanUseIOStringCallback", e),
+    }
+    Ok(())
+}
+
+// Loads libStructural from path and its initial state, restored from saved when there is one.
+// Sending Event::Init is left to the caller, so it can be recorded or replayed like any other
+// event. Errors from here on are also appended to error_log.
+pub fn test_lean(
+    path: &Path,
+    saved: Option<&mut SavedState>,
+    error_log: Option<PathBuf>,
+) -> Box<gui_api::Interpreter> {
     println!("size of LEANOKCtor: {}", mem::size_of::<LeanOKCtor>());
     println!("size of LeanBoxedU64 {}", mem::size_of::<LeanBoxedU64>());
     println!("size of LeanOKU64Ctor: {}", mem::size_of::<LeanOKU64Ctor>());
@@ -384,65 +430,56 @@ pub fn test_lean() -> gui_api::Interpreter {
         mem::size_of::<LeanOnEventClosure>()
     );
 
+    // boxed, the Gui's external points at it for as long as it lives
+    let mut interp = Box::new(gui_api::Interpreter {
+        effects: gui_api::Effects {
+            next_id: 0, // main moves it past its own columns before Init
+            next_timer: 0,
+            queue: Vec::new(),
+            app_state: ().into_lean(),
+        },
+        committed: true,
+        lib: None,
+        errors: Vec::new(),
+        log: None,
+        gui: None,
+        error_log,
+    });
+
+    // Any failure here leaves the interpreter unloaded: the window still comes up, showing
+    // the error, it just never calls into Lean until the library is rebuilt.
     unsafe {
         lean_initialize_runtime_module();
-        let res = initialize_Structural(1, LEAN_UNIT);
-        if (*res).m_tag == 0 {
-            lean_dec_ref(res);
-        } else {
-            println!("failed to load lean: {:?}", res);
-            lean_io_result_show_error(res);
-            lean_dec_ref(res);
-            // return; // FIXME just while experimenting
-        }
+        let lib = Structural::open(path).and_then(|lib| lib.init().map(|_| lib));
         lean_io_mark_end_initialization();
-
-        let a = leans_answer(LEAN_UNIT);
-        println!("Lean's answer: {}", a);
-        // let b = leans_other_answer(12);
-        // println!("Lean's other answer: {}", b);
-        let cb = mk_closure();
-        let r = lean_use_callback(cb);
-        println!("Lean's callback: {}", r);
-
-        let cbio = mk_io_closure();
-        let r2 = lean_use_io_callback(cbio) as *mut LeanOKCtor; // todo case check?
-        println!("Lean's io callback: {}", (*r2).m_objs_0 >> 1); // toodo unwrap
-        lean_dec_ref(r2 as *mut LeanObject);
-
-        let cbios = mk_io_string_closure();
-        let r3 = lean_use_io_string_callback(cbios, LEAN_UNIT) as *mut LeanOKStringCtor;
-        println!("Lean's io string: {}", str_from_lean((*r3).m_objs_0));
-        println!(
-            "Lean's refcounts: {}, {}",
-            (*r3).m_header.m_rc,
-            (*(*r3).m_objs_0).m_header.m_rc
-        );
-        lean_dec_ref(r3 as *mut LeanObject);
-
-        let init_state_io = lean_on_init(LEAN_UNIT);
-        let init_state = (*init_state_io).m_objs_0;
-        lean_inc_ref(init_state);
-        lean_dec_ref(init_state_io as *mut LeanObject);
-
-        let mut interp = gui_api::Interpreter {
-            effects: gui_api::Effects {
-                next_id: 2, // FIXME, just have to cols hardcoded already
-                new_columns: BTreeMap::new(),
-                text: HashMap::new(),
-                clip: HashMap::new(),
-                animate: HashMap::new(),
-                app_state: init_state,
-                should_quit: false,
-            },
-            committed: true,
+        let lib = match lib {
+            Ok(lib) => lib,
+            Err(e) => {
+                interp.report_error("libStructural", e);
+                return interp;
+            }
         };
 
-        gui_api::send_event_to_lean(&mut interp, 0, 0);
+        if let Err(e) = run_experiments(&lib, &mut interp) {
+            interp.report_error("experiments", e);
+        }
 
-        // let cls: *mut Closure<gui_api::EventCallback> = gui_api::mk_on_event(&mut interp);
-        // let ce = gui_api::mk_clear_effects(&mut interp);
-        // lean_use_on_event(LEAN_UNIT, cls, ce, LEAN_UNIT);
+        let restored = match saved.and_then(|saved| saved.restore(&lib)) {
+            Some(Ok(state)) => Some(state),
+            Some(Err(e)) => {
+                interp.report_error("leanLoadState", e);
+                None
+            }
+            None => None,
+        };
+        match restored.map_or_else(|| lib.initial_state(), Ok) {
+            Ok(init_state) => interp.effects.app_state = init_state,
+            Err(e) => {
+                interp.report_error("leanOnInit", e);
+                return interp;
+            }
+        }
+        interp.lib = Some(lib);
         interp
     }
 }
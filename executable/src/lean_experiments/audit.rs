@@ -0,0 +1,84 @@
+use super::lean_ref::is_scalar;
+use super::LeanObject;
+use std::collections::BTreeMap;
+use std::panic::Location;
+use std::sync::atomic::{AtomicIsize, Ordering};
+use std::sync::Mutex;
+
+// Debug builds keep count of the Lean objects Rust is responsible for: every reference a
+// LeanRef holds, keyed by object and remembering where it was taken, and the interpreter
+// external objects, which Lean tells us it's freeing through finalize. Whatever is left once
+// the interpreter lets go at shutdown is a leak. In release builds these do nothing.
+
+const AUDITING: bool = cfg!(debug_assertions);
+
+static HELD: Mutex<BTreeMap<usize, Vec<&'static Location<'static>>>> = Mutex::new(BTreeMap::new());
+static EXTERNALS: AtomicIsize = AtomicIsize::new(0);
+
+#[track_caller]
+pub fn acquire(ptr: *mut LeanObject) {
+    if AUDITING && !ptr.is_null() && !is_scalar(ptr) {
+        HELD.lock()
+            .unwrap()
+            .entry(ptr as usize)
+            .or_default()
+            .push(Location::caller());
+    }
+}
+
+pub fn release(ptr: *mut LeanObject) {
+    if AUDITING && !ptr.is_null() && !is_scalar(ptr) {
+        let mut held = HELD.lock().unwrap();
+        if let Some(sites) = held.get_mut(&(ptr as usize)) {
+            sites.pop();
+            if sites.is_empty() {
+                held.remove(&(ptr as usize));
+            }
+        }
+    }
+}
+
+pub fn external_made() {
+    if AUDITING {
+        EXTERNALS.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+pub fn external_freed() {
+    if AUDITING {
+        EXTERNALS.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+// Prints what's still held and returns how many references and externals that is.
+pub fn report() -> usize {
+    if !AUDITING {
+        return 0;
+    }
+    let held = HELD.lock().unwrap();
+    let mut leaks = 0;
+    for (ptr, sites) in held.iter() {
+        let o = *ptr as *mut LeanObject;
+        // still referenced by us, so still there to look at
+        let (rc, tag) = unsafe { ((*o).m_rc, (*o).m_tag) };
+        let sites: Vec<String> = sites.iter().map(|s| s.to_string()).collect();
+        println!(
+            "leak: {:?} (tag {}, rc {}) held from {}",
+            o,
+            tag,
+            rc,
+            sites.join(", ")
+        );
+        leaks += sites.len();
+    }
+    let externals = EXTERNALS.load(Ordering::Relaxed);
+    if externals != 0 {
+        println!(
+            "leak: {} interpreter external objects never finalized",
+            externals
+        );
+    }
+    leaks += externals.unsigned_abs();
+    println!("refcount audit: {} leaked", leaks);
+    leaks
+}
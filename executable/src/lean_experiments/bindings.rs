@@ -0,0 +1,151 @@
+use super::gui_api::{EffectDecl, EFFECTS, EVENT_LEAN, ON_EVENT_TYPE};
+use super::lean_ref::LeanRef;
+use super::structural::Structural;
+use super::LEAN_UNIT;
+
+// Symbols Rust calls in libStructural, an old library missing one gets a message instead of a
+// crash.
+pub const REQUIRED_SYMBOLS: &[&str] = &[
+    "initialize_Structural",
+    "lean_on_init",
+    "lean_on_event",
+    "lean_gui_abi",
+];
+
+fn field_type(effect: &EffectDecl) -> String {
+    let mut parts: Vec<&str> = effect.params.iter().map(|p| p.lean_type).collect();
+    let result = format!("IO {}", effect.result);
+    parts.push(&result);
+    parts.join(" → ")
+}
+
+// Every signature Rust relies on, one per line. Generated into Gui.lean as guiAbi and compared
+// against the loaded library at startup.
+pub fn gui_abi() -> String {
+    // comments aren't part of it
+    let mut lines: Vec<String> = EVENT_LEAN
+        .lines()
+        .map(|line| line.split(" --").next().unwrap().trim_end().to_owned())
+        .collect();
+    lines.push(format!("leanOnEvent : {}", ON_EVENT_TYPE));
+    for effect in EFFECTS {
+        lines.push(format!("{} : {}", effect.name, field_type(effect)));
+    }
+    lines.join("\n")
+}
+
+fn lean_string_literal(s: &str) -> String {
+    let escaped = s
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n");
+    format!("\"{}\"", escaped)
+}
+
+// The contents of lean/Structural/Gui.lean.
+pub fn gui_lean_source() -> String {
+    let mut out = String::new();
+    out.push_str(
+        "-- Generated by `tabularasa --emit-lean` from EFFECTS in gui_api.rs. Don't edit by hand.\n\n",
+    );
+    out.push_str(
+        "/-!
+Typed bindings for the effects Rust hands to `leanOnEvent`.
+
+Rust builds the `Gui` once and hands the same one to every event (see `mk_gui` in
+gui_api.rs), with the closures in field order. Scripts run in `GuiM` and call the
+wrappers below instead of threading each closure through by hand.
+-/
+
+namespace Structural
+
+abbrev ColumnId := UInt64
+abbrev TimerId := UInt64
+
+",
+    );
+    out.push_str(EVENT_LEAN);
+    out.push_str("\n\nstructure Gui (σ : Type) where\n");
+    for effect in EFFECTS {
+        out.push_str(&format!("  {} : {}", effect.name, field_type(effect)));
+        if let Some(doc) = effect.doc {
+            out.push_str(&format!(" -- {}", doc));
+        }
+        out.push('\n');
+    }
+    out.push_str(&format!(
+        "
+abbrev GuiM (σ : Type) := ReaderT (Gui σ) IO
+
+-- What `leanOnEvent` must be, for the script's State σ.
+abbrev LeanOnEvent (σ : Type) := {}
+
+-- Optional, `leanSaveState` and `leanLoadState` keep the state between launches in a format of
+-- the script's choosing.
+abbrev LeanSaveState (σ : Type) := σ → IO String
+abbrev LeanLoadState (σ : Type) := String → IO σ
+
+-- Checked against the Rust side when libStructural is loaded.
+@[export lean_gui_abi]
+def guiAbi (_ : Unit) : String :=
+  {}
+
+namespace GuiM
+
+variable {{σ : Type}}
+
+",
+        ON_EVENT_TYPE,
+        lean_string_literal(&gui_abi())
+    ));
+    for effect in EFFECTS {
+        let params: Vec<String> = effect
+            .params
+            .iter()
+            .map(|p| match p.default {
+                Some(d) => format!("({} : {} := {})", p.name, p.lean_type, d),
+                None => format!("({} : {})", p.name, p.lean_type),
+            })
+            .collect();
+        let args: Vec<&str> = effect.params.iter().map(|p| p.name).collect();
+        let mut def = format!("def {}", effect.name);
+        for p in &params {
+            def.push(' ');
+            def.push_str(p);
+        }
+        let mut call = format!("(← read).{}", effect.name);
+        for a in &args {
+            call.push(' ');
+            call.push_str(a);
+        }
+        out.push_str(&format!(
+            "{} : GuiM σ {} := do\n  {}\n",
+            def, effect.result, call
+        ));
+    }
+    out.push_str("\nend GuiM\n\nend Structural\n");
+    out
+}
+
+// Must run after initialize_Structural.
+pub fn check_structural(lib: &Structural) -> Result<(), String> {
+    let theirs = unsafe { LeanRef::<String>::from_owned((lib.gui_abi)(LEAN_UNIT))? };
+    let ours = gui_abi();
+    if theirs.as_str() == ours {
+        return Ok(());
+    }
+    let ours_lines: Vec<&str> = ours.lines().collect();
+    let mismatched: Vec<String> = theirs
+        .as_str()
+        .lines()
+        .zip(ours_lines.iter())
+        .filter(|(t, o)| t != *o)
+        .map(|(t, o)| format!("  Lean: {}\n  Rust: {}", t, o))
+        .collect();
+    Err(format!(
+        "libStructural was built against different effects, regenerate Gui.lean with --emit-lean and rebuild it ({} vs {} signatures)\n{}",
+        theirs.as_str().lines().count(),
+        ours_lines.len(),
+        mismatched.join("\n")
+    ))
+}
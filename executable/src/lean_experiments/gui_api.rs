@@ -1,79 +1,312 @@
+use crate::aggregate::AggregateKind;
+use crate::animation::Easing;
+use crate::export::ExportFormat;
+use crate::grid::{self, Cell, CellFormat};
+use crate::layout;
 use crate::lean_experiments;
-use crate::lean_experiments::{
-    lean_dec_ref, str_from_lean, Closure, LeanBoxedU64, LeanExternalObject, LeanOKCtor,
-    LeanOKU64Ctor, LeanObject, LeanString,
-};
+use crate::lean_experiments::audit;
+use crate::lean_experiments::lean_ref::{self, Any, Ctor, External, IntoLean, LeanRef, Scalar};
+use crate::lean_experiments::session::{Entry, SessionLog};
+use crate::lean_experiments::structural::Structural;
+use crate::lean_experiments::{LeanExternalObject, LeanObject};
+use crate::theme::{Rgba, Role, Theme};
 use crossbeam::atomic::AtomicCell;
-use num_enum::TryFromPrimitive;
-use std::collections::{BTreeMap, HashMap};
-use std::convert::TryFrom;
-
-use super::LeanBoxedFloat;
-
-#[link(name = "Structural")]
-extern "C" {
-    fn lean_on_event(
-        evt: u8,
-        st: *mut LeanObject,
-        ch: u32,
-        set_app_state: *mut Closure<SetAppState>,
-        fresh_column: *mut Closure<FreshColumn>,
-        push_line: *mut Closure<PushLine>,
-        reset_text: *mut Closure<ResetText>,
-        set_clip: *mut Closure<SetClip>,
-        remove_clip: *mut Closure<RemoveClip>,
-        animate: *mut Closure<Animate>,
-        quit: *mut Closure<Quit>,
-        io: libc::uintptr_t,
-    ) -> *mut LeanOKCtor;
-}
+use serde::{Deserialize, Serialize};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use tabularasa_macros::lean_effect;
 
 #[repr(C)]
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Vec2 {
     pub x: f32,
     pub y: f32,
 }
 
 // Pull into shared types higher up?
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Clip {
     pub pos: Vec2,
     pub size: Vec2,
 }
 
-#[derive(Debug)]
-pub enum AppendMode {
-    Append,
-    Replace,
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GridViewSpec {
+    pub pos: Vec2,
+    pub size: Vec2,
+    pub frozen_rows: usize,
+    pub pinned_columns: usize,
+}
+
+pub type ColID = u64;
+pub type TimerId = u64;
+
+// What a script asked for, already parsed. perform_effects applies them in the order they were
+// made, and they serialize so a session can be logged and replayed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Effect {
+    FreshColumn {
+        col: ColID,
+        pos: Vec2,
+    },
+    PushLine {
+        col: ColID,
+        line: String,
+    },
+    ResetText {
+        col: ColID,
+    },
+    SetClip {
+        col: ColID,
+        clip: Option<Clip>, // None removes it
+    },
+    Animate {
+        col: ColID,
+        to: Vec2,
+        duration: f32,
+        easing: Easing,
+        after: bool, // queued after the column's current animation instead of interrupting it
+    },
+    AnimateClip {
+        col: ColID,
+        to: Clip,
+        duration: f32,
+        easing: Easing,
+        after: bool,
+    },
+    AnimateColor {
+        col: ColID,
+        to: [f32; 4], // rgba, in place of its role's color until the role is set again
+        duration: f32,
+        easing: Easing,
+        after: bool,
+    },
+    Fade {
+        col: ColID,
+        to: f32, // opacity, 0 to 1
+        duration: f32,
+        easing: Easing,
+        after: bool,
+    },
+    SetColumnRole {
+        col: ColID,
+        role: Role,
+    },
+    SetTheme(Theme),
+    AnimateScroll {
+        to: Vec2,
+        duration: f32,
+        easing: Easing,
+        after: bool,
+    },
+    PushCell {
+        col: ColID,
+        cell: Cell,
+    },
+    SetFormat {
+        col: ColID,
+        format: CellFormat,
+    },
+    SetHeader {
+        col: ColID,
+        name: String,
+    },
+    SetShownRows(Option<Vec<usize>>), // None shows every row as pushed
+    SetSelection {
+        rows: Vec<usize>,
+        columns: Vec<ColID>,
+    },
+    ExportGrid {
+        format: ExportFormat,
+        path: String,
+    },
+    SetFooter {
+        col: ColID,
+        kinds: Vec<AggregateKind>,
+    },
+    SetGridView(GridViewSpec),
+    SetLayout(Option<layout::Node>), // None goes back to positions from Lean
+    RequestTimer {
+        timer: TimerId,
+        delay: f64,
+        every: Option<f64>, // None fires once
+    },
+    CancelTimer {
+        timer: TimerId,
+    },
+    RequestFrame,
+    Quit,
 }
 
-type ColID = u64;
+impl Effect {
+    // The column it changes, if it's about one.
+    pub fn col(&self) -> Option<ColID> {
+        match self {
+            Effect::FreshColumn { col, .. }
+            | Effect::PushLine { col, .. }
+            | Effect::ResetText { col }
+            | Effect::SetClip { col, .. }
+            | Effect::Animate { col, .. }
+            | Effect::AnimateClip { col, .. }
+            | Effect::AnimateColor { col, .. }
+            | Effect::Fade { col, .. }
+            | Effect::SetColumnRole { col, .. }
+            | Effect::PushCell { col, .. }
+            | Effect::SetFormat { col, .. }
+            | Effect::SetHeader { col, .. }
+            | Effect::SetFooter { col, .. } => Some(*col),
+            _ => None,
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct Effects {
     pub next_id: u64,
-    pub new_columns: BTreeMap<ColID, Vec2>,
-    pub text: HashMap<ColID, (AppendMode, Vec<String>)>,
-    pub clip: HashMap<ColID, Option<Clip>>,
-    pub animate: HashMap<ColID, (Vec2, f32)>,
-    pub app_state: *mut LeanObject,
-    pub should_quit: bool,
+    pub next_timer: TimerId,
+    pub queue: Vec<Effect>, // since the last perform_effects, oldest first
+    pub app_state: LeanRef<Any>,
 }
 
 #[derive(Debug)]
 pub struct Interpreter {
     pub effects: Effects,
     pub committed: bool,
+    pub lib: Option<Structural>, // None when Lean failed to initialize, events aren't sent
+    pub errors: Vec<String>,     // everything reported so far, oldest first
+    pub log: Option<SessionLog>, // with --record, every event and its effects
+    pub gui: Option<LeanRef<Ctor>>, // made by the first event and kept, see gui
+    pub error_log: Option<PathBuf>, // errors are appended here too, they outlive the window
+}
+
+impl Interpreter {
+    pub fn report_error(&mut self, context: &str, e: String) {
+        let message = format!("{}: {}", context, e);
+        println!("Lean error: {}", message);
+        if let Some(path) = &self.error_log {
+            let line = format!("{} {}\n", grid::iso_timestamp(grid::unix_now()), message);
+            let written = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .and_then(|mut file| file.write_all(line.as_bytes()));
+            if let Err(e) = written {
+                println!("not logged to {}: {}", path.display(), e);
+            }
+        }
+        self.errors.push(message);
+    }
+
+    // Lets go of the state and reports anything Rust still holds, see audit. No more events
+    // are sent after this.
+    pub fn shutdown(&mut self) {
+        self.lib = None;
+        // the last reference to the closures, so the external is finalized here
+        self.gui = None;
+        self.effects.app_state = ().into_lean();
+        audit::report();
+    }
 }
 
 #[repr(u8)]
-#[derive(Debug, Eq, PartialEq, TryFromPrimitive)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum MouseKind {
+    Down,
+    Up,
+    Move,
+}
+
+// Mirrors EVENT_LEAN, the constructors are in the same order.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Event {
     Init,
-    Char,
-    Up,
-    Down,
+    Char(char),
+    Key {
+        name: String,
+        down: bool,
+    },
+    Mouse {
+        kind: MouseKind,
+        x: f32,
+        y: f32,
+        button: u8,
+    },
+    Resize {
+        width: f32,
+        height: f32,
+    },
+    Tick {
+        time: f64,
+    },
+    DataReady {
+        col: ColID,
+    },
+    Timer {
+        timer: TimerId,
+    },
+    Splitter {
+        name: String,
+        ratio: f32,
+    },
+}
+
+// Generated into Gui.lean, and part of the ABI check.
+pub const EVENT_LEAN: &str = "inductive MouseKind where
+  | down | up | move
+  deriving Repr, BEq
+
+inductive Event where
+  | init -- sent once, right after leanOnInit
+  | char (c : Char)
+  | key (name : String) (down : Bool) -- name is miniquad's KeyCode, e.g. \"Up\", \"Enter\", \"A\"
+  | mouse (kind : MouseKind) (x y : Float) (button : UInt8) -- 0 left, 1 right, 2 middle, 3 other
+  | resize (width height : Float) -- the window's new size, positions are all logical pixels
+  | tick (time : Float) -- the frame's time in seconds, once per requestFrame
+  | dataReady (col : ColumnId) -- a grid column's footer summary was recomputed
+  | timer (id : TimerId) -- from requestTimer
+  | splitter (name : String) (ratio : Float) -- a splitter was dragged and let go, keep ratio in the layout
+  deriving Repr";
+
+impl Event {
+    pub fn to_lean(&self) -> LeanRef<Ctor> {
+        match self {
+            Event::Init => LeanRef::<Ctor>::nullary(0),
+            Event::Char(c) => LeanRef::<Ctor>::with_scalars(1, vec![], &[Scalar::U32(*c as u32)]),
+            Event::Key { name, down } => LeanRef::<Ctor>::with_scalars(
+                2,
+                vec![LeanRef::<String>::new(name).into_any()],
+                &[Scalar::U8(*down as u8)],
+            ),
+            Event::Mouse { kind, x, y, button } => LeanRef::<Ctor>::with_scalars(
+                3,
+                vec![],
+                &[
+                    Scalar::U8(*kind as u8),
+                    Scalar::F64(*x as f64),
+                    Scalar::F64(*y as f64),
+                    Scalar::U8(*button),
+                ],
+            ),
+            Event::Resize { width, height } => LeanRef::<Ctor>::with_scalars(
+                4,
+                vec![],
+                &[Scalar::F64(*width as f64), Scalar::F64(*height as f64)],
+            ),
+            Event::Tick { time } => LeanRef::<Ctor>::with_scalars(5, vec![], &[Scalar::F64(*time)]),
+            Event::DataReady { col } => {
+                LeanRef::<Ctor>::with_scalars(6, vec![], &[Scalar::U64(*col)])
+            }
+            Event::Timer { timer } => {
+                LeanRef::<Ctor>::with_scalars(7, vec![], &[Scalar::U64(*timer)])
+            }
+            Event::Splitter { name, ratio } => LeanRef::<Ctor>::with_scalars(
+                8,
+                vec![LeanRef::<String>::new(name).into_any()],
+                &[Scalar::F64(*ratio as f64)],
+            ),
+        }
+    }
 }
 
 const LEAN_UNIT: libc::uintptr_t = (0 << 1) | 1;
@@ -86,6 +319,7 @@ static INTERPRETER_CLASS: AtomicCell<Option<ClassPointer>> = AtomicCell::new(Non
 
 extern "C" fn finalize(_this: *mut libc::c_void) {
 //    println!("finalize called");
+    audit::external_freed();
 }
 
 extern "C" fn for_each(_this: *mut libc::c_void, _obj: *mut LeanObject) {
@@ -105,318 +339,570 @@ pub fn register_interpreter() {
     }
 }
 
-// todo Result. Also lean will GC these wrappers after use I think, so might have to fuss with lifetimes
+// todo Result. Lean frees it, calling finalize, once the last closure over it is freed.
 pub fn mk_external(interp: &mut Interpreter) -> *mut lean_experiments::LeanExternalObject {
     register_interpreter();
     let cls = INTERPRETER_CLASS.load().unwrap().0 as *mut lean_experiments::LeanExternalClass;
+    audit::external_made();
     lean_experiments::mk_external_object(cls, interp as *mut _ as *mut libc::c_void)
 }
 
-pub fn send_event_to_lean(interp: &mut Interpreter, evt: u8, ch: u32) {
-    // silently doesn't call if these aren't rebuilt. Swallowing some error after GC'd?
-    let sap = mk_set_app_state(interp);
-    let fc = mk_fresh_column(interp);
-    let pl = mk_push_line(interp);
-    let rt = mk_reset_text(interp);
-    let sc = mk_set_clip(interp);
-    let rc = mk_remove_clip(interp);
-    let an = mk_animate(interp);
-    let qt = mk_quit(interp);
-    unsafe {
-        lean_on_event(
-            evt,
-            interp.effects.app_state,
-            ch,
-            sap,
-            fc,
-            pl,
-            rt,
-            sc,
-            rc,
-            an,
-            qt,
+pub struct EffectParam {
+    pub name: &'static str,
+    pub lean_type: &'static str,
+    pub default: Option<&'static str>,
+}
+
+// One field of the Lean `Gui` structure, generated by lean_effect.
+pub struct EffectDecl {
+    pub name: &'static str,
+    pub params: &'static [EffectParam],
+    pub result: &'static str,
+    pub doc: Option<&'static str>,
+    pub mk: fn(*mut LeanExternalObject) -> *mut LeanObject, // owns the reference it's given
+}
+
+// The Gui structure's fields in order. Structural/Gui.lean is generated from this with
+// `tabularasa --emit-lean`, so adding an effect is adding it here and regenerating.
+pub const EFFECTS: &[EffectDecl] = &[
+    SET_APP_STATE,
+    FRESH_COLUMN,
+    PUSH_LINE,
+    RESET_TEXT,
+    SET_CLIP,
+    REMOVE_CLIP,
+    ANIMATE,
+    ANIMATE_AFTER,
+    ANIMATE_CLIP,
+    ANIMATE_CLIP_AFTER,
+    ANIMATE_COLOR,
+    ANIMATE_COLOR_AFTER,
+    ANIMATE_SCROLL,
+    ANIMATE_SCROLL_AFTER,
+    SET_OPACITY,
+    FADE,
+    FADE_AFTER,
+    SET_COLUMN_ROLE,
+    SET_THEME,
+    PUSH_CELL,
+    SET_FORMAT,
+    SET_HEADER,
+    SET_SHOWN_ROWS,
+    SHOW_ALL_ROWS,
+    SET_SELECTION,
+    EXPORT_GRID,
+    SET_FOOTER,
+    SET_GRID_VIEW,
+    SET_LAYOUT,
+    REQUEST_TIMER,
+    CANCEL_TIMER,
+    REQUEST_FRAME,
+    QUIT,
+];
+
+// The type of leanOnEvent, given the script's State as σ.
+pub const ON_EVENT_TYPE: &str = "Event → σ → Gui σ → IO Unit";
+
+pub fn mk_gui(interp: &mut Interpreter) -> LeanRef<Ctor> {
+    let ext = unsafe { LeanRef::<External>::from_owned(mk_external(interp) as *mut LeanObject) }
+        .expect("mk_gui: the interpreter external");
+    // each closure owns a reference to the external, ours goes at the end
+    let fields = EFFECTS
+        .iter()
+        .map(|e| {
+            let closure = (e.mk)(ext.clone().into_raw() as *mut LeanExternalObject);
+            unsafe { LeanRef::<Any>::from_owned(closure) }.expect("mk_gui: an effect closure")
+        })
+        .collect();
+    LeanRef::<Ctor>::new(0, fields)
+}
+
+// The Gui every leanOnEvent is handed: the effect closures, all closing over one external that
+// points back at the interpreter. Made once, the interpreter is boxed from test_lean on so it
+// never moves out from under the external.
+fn gui(interp: &mut Interpreter) -> LeanRef<Ctor> {
+    if interp.gui.is_none() {
+        interp.gui = Some(mk_gui(interp));
+    }
+    interp.gui.as_ref().unwrap().clone()
+}
+
+// Effects made before a script throws are kept, the same as any other IO.
+pub fn send_event_to_lean(interp: &mut Interpreter, event: &Event) {
+    let on_event = match &interp.lib {
+        Some(lib) => lib.on_event,
+        None => return,
+    };
+    let next_id = interp.effects.next_id;
+    let queued = interp.effects.queue.len();
+    let gui = gui(interp);
+    let res = unsafe {
+        lean_ref::take_io_result(on_event(
+            event.to_lean().into_raw(),
+            // Lean takes its own reference, ours stays with the interpreter
+            interp.effects.app_state.clone().into_raw(),
+            gui.into_raw(),
             LEAN_UNIT,
-        );
+        ))
+    };
+    if let Err(e) = res {
+        interp.report_error("leanOnEvent", e);
+    }
+    if let Some(log) = &mut interp.log {
+        let entry = Entry {
+            next_id,
+            event: event.clone(),
+            effects: interp.effects.queue[queued..].to_vec(),
+        };
+        if let Err(e) = log.record(&entry) {
+            println!("session log: {}", e);
+        }
     }
 }
 
-pub type EventCallback = extern "C" fn(*mut LeanObject, u8, *mut LeanObject) -> *mut LeanOKCtor;
+// Effects are plain functions of the interpreter and their arguments, lean_effect generates
+// the extern shim, closure type and mk_ function that mk_gui uses.
 
-pub extern "C" fn on_event(
-    interp: *mut LeanObject,
-    evt: u8,
-    _io: *mut LeanObject,
-) -> *mut lean_experiments::LeanOKCtor {
-    let e: Event = Event::try_from(evt >> 1).unwrap();
-    println!("Rust: on_event called with: {:?}", e);
-    let o = interp as *mut lean_experiments::LeanExternalObject;
-    unsafe {
-        let interp = (*o).m_data as *mut Interpreter;
-        println!("Found Interpreter: {:?}", (*interp));
-        (*interp).committed = !(*interp).committed;
+#[lean_effect]
+pub fn set_app_state(interp: &mut Interpreter, s: LeanRef<Any>) {
+    // the previous state is released here, Lean has its own reference if it still needs it
+    interp.effects.app_state = s;
+}
+
+#[lean_effect]
+pub fn fresh_column(interp: &mut Interpreter, x: f32, y: f32) -> ColID {
+    let col = interp.effects.next_id;
+    interp.effects.next_id = col + 1;
+    interp.effects.queue.push(Effect::FreshColumn {
+        col,
+        pos: Vec2 { x, y },
+    });
+    col
+}
+
+#[lean_effect]
+pub fn push_line(interp: &mut Interpreter, col: ColID, line: String) {
+    interp.effects.queue.push(Effect::PushLine { col, line });
+}
+
+#[lean_effect]
+pub fn reset_text(interp: &mut Interpreter, col: ColID) {
+    interp.effects.queue.push(Effect::ResetText { col });
+}
+
+#[lean_effect]
+pub fn set_clip(interp: &mut Interpreter, col: ColID, x: f32, y: f32, w: f32, h: f32) {
+    let clip = Some(Clip {
+        pos: Vec2 { x, y },
+        size: Vec2 { x: w, y: h },
+    });
+    interp.effects.queue.push(Effect::SetClip { col, clip });
+}
+
+#[lean_effect]
+pub fn remove_clip(interp: &mut Interpreter, col: ColID) {
+    interp
+        .effects
+        .queue
+        .push(Effect::SetClip { col, clip: None });
+}
+
+fn push_animate(
+    interp: &mut Interpreter,
+    col: ColID,
+    to: Vec2,
+    duration: f32,
+    easing: &str,
+    after: bool,
+) {
+    match Easing::parse(easing) {
+        Ok(easing) => interp.effects.queue.push(Effect::Animate {
+            col,
+            to,
+            duration,
+            easing,
+            after,
+        }),
+        Err(e) => interp.report_error("animate", format!("column {}: {}", col, e)),
     }
-    //    interp.cur_event = e;
-    let r = lean_experiments::lean_io_result_mk_ok(0);
-    println!("Made ret value");
-    r
 }
 
-pub fn mk_on_event(interp: &mut Interpreter) -> *mut Closure<EventCallback> {
-    lean_experiments::mk_closure_2(on_event, mk_external(interp), 3)
+/// moves the column from wherever it is now, easing is linear/cubic-in/cubic-out/cubic/ease-in-out/spring
+#[lean_effect]
+pub fn animate(
+    interp: &mut Interpreter,
+    col: ColID,
+    x: f32,
+    y: f32,
+    duration: f32,
+    #[lean_default("linear")] easing: String,
+) {
+    push_animate(interp, col, Vec2 { x, y }, duration, &easing, false);
 }
 
-pub type SetAppState =
-    extern "C" fn(*mut LeanObject, *mut LeanObject, *mut LeanObject) -> *mut LeanOKCtor;
+/// like animate, once the column's animations so far are done
+#[lean_effect]
+pub fn animate_after(
+    interp: &mut Interpreter,
+    col: ColID,
+    x: f32,
+    y: f32,
+    duration: f32,
+    #[lean_default("linear")] easing: String,
+) {
+    push_animate(interp, col, Vec2 { x, y }, duration, &easing, true);
+}
 
-pub extern "C" fn set_app_state(
-    interp: *mut LeanObject,
-    state: *mut LeanObject,
-    _io: *mut LeanObject,
-) -> *mut LeanOKCtor {
-    let o = interp as *mut LeanExternalObject;
-    unsafe {
-        let interp = (*o).m_data as *mut Interpreter;
-        (*interp).effects.app_state = state;
+fn push_animate_clip(
+    interp: &mut Interpreter,
+    col: ColID,
+    to: Clip,
+    duration: f32,
+    easing: &str,
+    after: bool,
+) {
+    match Easing::parse(easing) {
+        Ok(easing) => interp.effects.queue.push(Effect::AnimateClip {
+            col,
+            to,
+            duration,
+            easing,
+            after,
+        }),
+        Err(e) => interp.report_error("animate_clip", format!("column {}: {}", col, e)),
     }
-    lean_experiments::lean_io_result_mk_ok(0)
 }
 
-pub fn mk_set_app_state(interp: &mut Interpreter) -> *mut Closure<SetAppState> {
-    lean_experiments::mk_closure_2(set_app_state, mk_external(interp), 3)
+/// x y w h, from the column's current clip, an unclipped one jumps straight there
+#[lean_effect]
+pub fn animate_clip(
+    interp: &mut Interpreter,
+    col: ColID,
+    x: f32,
+    y: f32,
+    w: f32,
+    h: f32,
+    duration: f32,
+    #[lean_default("linear")] easing: String,
+) {
+    let to = Clip {
+        pos: Vec2 { x, y },
+        size: Vec2 { x: w, y: h },
+    };
+    push_animate_clip(interp, col, to, duration, &easing, false);
 }
 
-pub type FreshColumn = extern "C" fn(
-    *mut LeanObject,
-    *mut LeanBoxedFloat,
-    *mut LeanBoxedFloat,
-    *mut LeanObject,
-) -> *mut LeanOKU64Ctor;
+/// like animate_clip, once the column's clip animations so far are done
+#[lean_effect]
+pub fn animate_clip_after(
+    interp: &mut Interpreter,
+    col: ColID,
+    x: f32,
+    y: f32,
+    w: f32,
+    h: f32,
+    duration: f32,
+    #[lean_default("linear")] easing: String,
+) {
+    let to = Clip {
+        pos: Vec2 { x, y },
+        size: Vec2 { x: w, y: h },
+    };
+    push_animate_clip(interp, col, to, duration, &easing, true);
+}
 
-pub extern "C" fn fresh_column(
-    interp: *mut LeanObject,
-    pos_x: *mut LeanBoxedFloat,
-    pos_y: *mut LeanBoxedFloat,
-    _io: *mut LeanObject,
-) -> *mut LeanOKU64Ctor {
-    let o = interp as *mut LeanExternalObject;
-    unsafe {
-        let interp = (*o).m_data as *mut Interpreter;
-        let id = (*interp).effects.next_id;
-        let ub_pos_x = (*pos_x).m_obj as f32;
-        let ub_pos_y = (*pos_y).m_obj as f32;
-        lean_dec_ref(pos_x as *mut LeanObject);
-        lean_dec_ref(pos_y as *mut LeanObject);
-        let old = (*interp).effects.new_columns.insert(
-            id,
-            Vec2 {
-                x: ub_pos_x,
-                y: ub_pos_y,
-            },
-        );
-        assert!(old.is_none());
-        (*interp).effects.next_id = id + 1;
-        // println!("Got to the fresh_column, {},{}", ub_pos_x, ub_pos_y);
-        // println!("effects: {:?}", (*interp).effects);
-        lean_experiments::lean_io_result_mk_u64_ok(id)
+fn push_animate_color(
+    interp: &mut Interpreter,
+    col: ColID,
+    color: &str,
+    duration: f32,
+    easing: &str,
+    after: bool,
+) {
+    match Rgba::parse(color).and_then(|to| Ok((to, Easing::parse(easing)?))) {
+        Ok((to, easing)) => interp.effects.queue.push(Effect::AnimateColor {
+            col,
+            to: to.0,
+            duration,
+            easing,
+            after,
+        }),
+        Err(e) => interp.report_error("animate_color", format!("column {}: {}", col, e)),
     }
 }
 
-pub fn mk_fresh_column(interp: &mut Interpreter) -> *mut Closure<FreshColumn> {
-    lean_experiments::mk_closure_2(fresh_column, mk_external(interp), 4)
+/// to a color like "#1a2b3c" or "#1a2b3cff", from the one its text is drawn in now
+#[lean_effect]
+pub fn animate_color(
+    interp: &mut Interpreter,
+    col: ColID,
+    color: String,
+    duration: f32,
+    #[lean_default("linear")] easing: String,
+) {
+    push_animate_color(interp, col, &color, duration, &easing, false);
+}
+
+/// like animate_color, once the column's color animations so far are done
+#[lean_effect]
+pub fn animate_color_after(
+    interp: &mut Interpreter,
+    col: ColID,
+    color: String,
+    duration: f32,
+    #[lean_default("linear")] easing: String,
+) {
+    push_animate_color(interp, col, &color, duration, &easing, true);
 }
 
-pub type PushLine = extern "C" fn(
-    *mut LeanObject,
-    *mut LeanBoxedU64,
-    *mut LeanString,
-    *mut LeanObject,
-) -> *mut LeanOKCtor;
+/// 0 transparent to 1 opaque, straight away
+#[lean_effect]
+pub fn set_opacity(interp: &mut Interpreter, col: ColID, opacity: f32) {
+    interp.effects.queue.push(Effect::Fade {
+        col,
+        to: opacity,
+        duration: 0.0,
+        easing: Easing::Linear,
+        after: false,
+    });
+}
 
-pub extern "C" fn push_line(
-    interp: *mut LeanObject,
-    id: *mut LeanBoxedU64,
-    text: *mut LeanString,
-    _io: *mut LeanObject,
-) -> *mut LeanOKCtor {
-    let o = interp as *mut LeanExternalObject;
-    unsafe {
-        let interp = (*o).m_data as *mut Interpreter;
-        let ub_id = (*id).m_obj;
-        let entry = (*interp)
-            .effects
-            .text
-            .entry(ub_id)
-            .or_insert((AppendMode::Append, vec![]));
-        entry.1.push(str_from_lean(text).to_owned());
-        lean_dec_ref(id as *mut LeanObject);
-        lean_dec_ref(text as *mut LeanObject);
-        // println!("push_line: {:?}", (*interp).effects);
-        lean_experiments::lean_io_result_mk_ok(0)
+fn push_fade(
+    interp: &mut Interpreter,
+    col: ColID,
+    to: f32,
+    duration: f32,
+    easing: &str,
+    after: bool,
+) {
+    match Easing::parse(easing) {
+        Ok(easing) => interp.effects.queue.push(Effect::Fade {
+            col,
+            to,
+            duration,
+            easing,
+            after,
+        }),
+        Err(e) => interp.report_error("fade", format!("column {}: {}", col, e)),
     }
 }
 
-pub fn mk_push_line(interp: &mut Interpreter) -> *mut Closure<PushLine> {
-    lean_experiments::mk_closure_2(push_line, mk_external(interp), 4)
+/// to opacity over duration, from the column's current one
+#[lean_effect]
+pub fn fade(
+    interp: &mut Interpreter,
+    col: ColID,
+    to: f32,
+    duration: f32,
+    #[lean_default("linear")] easing: String,
+) {
+    push_fade(interp, col, to, duration, &easing, false);
 }
 
-pub type ResetText =
-    extern "C" fn(*mut LeanObject, *mut LeanBoxedU64, *mut LeanObject) -> *mut LeanOKCtor;
+/// like fade, once the column's fades so far are done
+#[lean_effect]
+pub fn fade_after(
+    interp: &mut Interpreter,
+    col: ColID,
+    to: f32,
+    duration: f32,
+    #[lean_default("linear")] easing: String,
+) {
+    push_fade(interp, col, to, duration, &easing, true);
+}
 
-pub extern "C" fn reset_text(
-    interp: *mut LeanObject,
-    id: *mut LeanBoxedU64,
-    _io: *mut LeanObject,
-) -> *mut LeanOKCtor {
-    let o = interp as *mut LeanExternalObject;
-    unsafe {
-        let interp = (*o).m_data as *mut Interpreter;
-        let ub_id = (*id).m_obj;
-        (*interp)
+/// the theme color its text is drawn in: text/muted/accent/selection/grid_line/error/background
+#[lean_effect]
+pub fn set_column_role(interp: &mut Interpreter, col: ColID, role: String) {
+    match Role::parse(&role) {
+        Ok(role) => interp
             .effects
-            .text
-            .insert(ub_id, (AppendMode::Replace, vec![]));
-        lean_dec_ref(id as *mut LeanObject);
-        // println!("reset_text: {:?}", (*interp).effects);
-        lean_experiments::lean_io_result_mk_ok(0)
+            .queue
+            .push(Effect::SetColumnRole { col, role }),
+        Err(e) => interp.report_error("set_column_role", format!("column {}: {}", col, e)),
     }
 }
 
-pub fn mk_reset_text(interp: &mut Interpreter) -> *mut Closure<ResetText> {
-    lean_experiments::mk_closure_2(reset_text, mk_external(interp), 3)
-}
-
-pub type SetClip = extern "C" fn(
-    *mut LeanObject,
-    *mut LeanBoxedU64,
-    *mut LeanBoxedFloat,
-    *mut LeanBoxedFloat,
-    *mut LeanBoxedFloat,
-    *mut LeanBoxedFloat,
-    *mut LeanObject,
-) -> *mut LeanOKCtor;
-
-pub extern "C" fn set_clip(
-    interp: *mut LeanObject,
-    id: *mut LeanBoxedU64,
-    pos_x: *mut LeanBoxedFloat,
-    pos_y: *mut LeanBoxedFloat,
-    width: *mut LeanBoxedFloat,
-    height: *mut LeanBoxedFloat,
-    _io: *mut LeanObject,
-) -> *mut LeanOKCtor {
-    let o = interp as *mut LeanExternalObject;
-    unsafe {
-        let interp = (*o).m_data as *mut Interpreter;
-        let ub_id = (*id).m_obj;
-        let ub_pos_x = (*pos_x).m_obj as f32;
-        let ub_pos_y = (*pos_y).m_obj as f32;
-        let ub_width = (*width).m_obj as f32;
-        let ub_height = (*height).m_obj as f32;
-        (*interp).effects.clip.insert(
-            ub_id,
-            Some(Clip {
-                pos: Vec2 {
-                    x: ub_pos_x,
-                    y: ub_pos_y,
-                },
-                size: Vec2 {
-                    x: ub_width,
-                    y: ub_height,
-                },
-            }),
-        );
-        lean_dec_ref(id as *mut LeanObject);
-        lean_dec_ref(pos_x as *mut LeanObject);
-        lean_dec_ref(pos_y as *mut LeanObject);
-        lean_dec_ref(width as *mut LeanObject);
-        lean_dec_ref(height as *mut LeanObject);
-        lean_experiments::lean_io_result_mk_ok(0)
+/// "light", "dark", or the path of a .json or .toml theme file
+#[lean_effect]
+pub fn set_theme(interp: &mut Interpreter, theme: String) {
+    match Theme::load(&theme) {
+        Ok(theme) => interp.effects.queue.push(Effect::SetTheme(theme)),
+        Err(e) => interp.report_error("set_theme", e),
+    }
+}
+
+fn push_animate_scroll(
+    interp: &mut Interpreter,
+    to: Vec2,
+    duration: f32,
+    easing: &str,
+    after: bool,
+) {
+    match Easing::parse(easing) {
+        Ok(easing) => interp.effects.queue.push(Effect::AnimateScroll {
+            to,
+            duration,
+            easing,
+            after,
+        }),
+        Err(e) => interp.report_error("animate_scroll", e),
     }
 }
 
-pub fn mk_set_clip(interp: &mut Interpreter) -> *mut Closure<SetClip> {
-    lean_experiments::mk_closure_2(set_clip, mk_external(interp), 7)
+/// scrolls the grid view to x y, clamped to its contents
+#[lean_effect]
+pub fn animate_scroll(
+    interp: &mut Interpreter,
+    x: f32,
+    y: f32,
+    duration: f32,
+    #[lean_default("linear")] easing: String,
+) {
+    push_animate_scroll(interp, Vec2 { x, y }, duration, &easing, false);
 }
 
-pub type RemoveClip =
-    extern "C" fn(*mut LeanObject, *mut LeanBoxedU64, *mut LeanObject) -> *mut LeanOKCtor;
+/// like animate_scroll, once the scrolling so far is done
+#[lean_effect]
+pub fn animate_scroll_after(
+    interp: &mut Interpreter,
+    x: f32,
+    y: f32,
+    duration: f32,
+    #[lean_default("linear")] easing: String,
+) {
+    push_animate_scroll(interp, Vec2 { x, y }, duration, &easing, true);
+}
 
-pub extern "C" fn remove_clip(
-    interp: *mut LeanObject,
-    id: *mut LeanBoxedU64,
-    _io: *mut LeanObject,
-) -> *mut LeanOKCtor {
-    let o = interp as *mut LeanExternalObject;
-    unsafe {
-        let interp = (*o).m_data as *mut Interpreter;
-        let ub_id = (*id).m_obj;
-        (*interp).effects.clip.insert(ub_id, None);
-        lean_dec_ref(id as *mut LeanObject);
-        lean_experiments::lean_io_result_mk_ok(0)
+/// kind is one of null/int/float/text/timestamp/bytes
+#[lean_effect]
+pub fn push_cell(interp: &mut Interpreter, col: ColID, kind: String, raw: String) {
+    match Cell::parse(&kind, &raw) {
+        Ok(cell) => interp.effects.queue.push(Effect::PushCell { col, cell }),
+        Err(e) => interp.report_error("push_cell", format!("column {}: {}", col, e)),
     }
 }
 
-pub fn mk_remove_clip(interp: &mut Interpreter) -> *mut Closure<RemoveClip> {
-    lean_experiments::mk_closure_2(remove_clip, mk_external(interp), 3)
-}
-
-pub type Animate = extern "C" fn(
-    *mut LeanObject,
-    *mut LeanBoxedU64,
-    *mut LeanBoxedFloat,
-    *mut LeanBoxedFloat,
-    *mut LeanBoxedFloat,
-    *mut LeanObject,
-) -> *mut LeanOKCtor;
-
-pub extern "C" fn animate(
-    interp: *mut LeanObject,
-    id: *mut LeanBoxedU64,
-    pos_x: *mut LeanBoxedFloat,
-    pos_y: *mut LeanBoxedFloat,
-    duration: *mut LeanBoxedFloat,
-    _io: *mut LeanObject,
-) -> *mut LeanOKCtor {
-    let o = interp as *mut LeanExternalObject;
-    unsafe {
-        let interp = (*o).m_data as *mut Interpreter;
-        let ub_id = (*id).m_obj;
-        let ub_pos_x = (*pos_x).m_obj as f32;
-        let ub_pos_y = (*pos_y).m_obj as f32;
-        let ub_duration = (*duration).m_obj as f32;
-        (*interp).effects.animate.insert(
-            ub_id,
-            (
-                Vec2 {
-                    x: ub_pos_x,
-                    y: ub_pos_y,
-                },
-                ub_duration,
-            ),
-        );
-        lean_dec_ref(id as *mut LeanObject);
-        lean_dec_ref(pos_x as *mut LeanObject);
-        lean_dec_ref(pos_y as *mut LeanObject);
-        lean_dec_ref(duration as *mut LeanObject);
-        lean_experiments::lean_io_result_mk_ok(0)
+/// e.g. "decimals=2 width=400", see CellFormat::parse
+#[lean_effect]
+pub fn set_format(interp: &mut Interpreter, col: ColID, spec: String) {
+    match CellFormat::parse(&spec) {
+        Ok(format) => interp.effects.queue.push(Effect::SetFormat { col, format }),
+        Err(e) => interp.report_error("set_format", format!("column {}: {}", col, e)),
     }
 }
 
-pub fn mk_animate(interp: &mut Interpreter) -> *mut Closure<Animate> {
-    lean_experiments::mk_closure_2(animate, mk_external(interp), 6)
+#[lean_effect]
+pub fn set_header(interp: &mut Interpreter, col: ColID, name: String) {
+    interp.effects.queue.push(Effect::SetHeader { col, name });
+}
+
+/// the rows the grid shows, as indices into the pushed cells, in the order Lean sorted them and
+/// leaving out the ones it filtered away; drawing and exports follow it
+#[lean_effect]
+pub fn set_shown_rows(interp: &mut Interpreter, rows: Vec<usize>) {
+    interp.effects.queue.push(Effect::SetShownRows(Some(rows)));
 }
 
-pub type Quit = extern "C" fn(*mut LeanObject, *mut LeanObject) -> *mut LeanOKCtor;
+/// back to every row in the order pushed
+#[lean_effect]
+pub fn show_all_rows(interp: &mut Interpreter) {
+    interp.effects.queue.push(Effect::SetShownRows(None));
+}
 
-pub extern "C" fn quit(interp: *mut LeanObject, _io: *mut LeanObject) -> *mut LeanOKCtor {
-    let o = interp as *mut LeanExternalObject;
-    unsafe {
-        let interp = (*o).m_data as *mut Interpreter;
-        (*interp).effects.should_quit = true;
-        lean_experiments::lean_io_result_mk_ok(0)
+/// what exportGrid writes, out of the shown rows and the grid columns, empty selects them all
+#[lean_effect]
+pub fn set_selection(interp: &mut Interpreter, rows: Vec<usize>, columns: Vec<ColID>) {
+    interp
+        .effects
+        .queue
+        .push(Effect::SetSelection { rows, columns });
+}
+
+/// format, path; the selection in the order shown
+#[lean_effect]
+pub fn export_grid(interp: &mut Interpreter, format: String, path: String) {
+    match ExportFormat::parse(&format) {
+        Ok(format) => interp
+            .effects
+            .queue
+            .push(Effect::ExportGrid { format, path }),
+        Err(e) => interp.report_error("export_grid", e),
+    }
+}
+
+/// e.g. "count sum avg", "" hides it
+#[lean_effect]
+pub fn set_footer(interp: &mut Interpreter, col: ColID, spec: String) {
+    match AggregateKind::parse_list(&spec) {
+        Ok(kinds) => interp.effects.queue.push(Effect::SetFooter { col, kinds }),
+        Err(e) => interp.report_error("set_footer", format!("column {}: {}", col, e)),
     }
 }
 
-pub fn mk_quit(interp: &mut Interpreter) -> *mut Closure<Quit> {
-    lean_experiments::mk_closure_2(quit, mk_external(interp), 2)
+/// x y w h frozenRows pinnedColumns
+#[lean_effect]
+pub fn set_grid_view(
+    interp: &mut Interpreter,
+    x: f32,
+    y: f32,
+    w: f32,
+    h: f32,
+    frozen_rows: usize,
+    pinned_columns: usize,
+) {
+    interp.effects.queue.push(Effect::SetGridView(GridViewSpec {
+        pos: Vec2 { x, y },
+        size: Vec2 { x: w, y: h },
+        frozen_rows,
+        pinned_columns,
+    }));
+}
+
+/// fires Event.timer after delay seconds, then every `every` seconds unless that's 0
+#[lean_effect]
+pub fn request_timer(
+    interp: &mut Interpreter,
+    delay: f64,
+    #[lean_default(0)] every: f64,
+) -> TimerId {
+    let timer = interp.effects.next_timer;
+    interp.effects.next_timer = timer + 1;
+    let every = if every > 0.0 { Some(every) } else { None };
+    interp.effects.queue.push(Effect::RequestTimer {
+        timer,
+        delay,
+        every,
+    });
+    timer
+}
+
+/// e.g. "hsplit(250 #3, 1fr vsplit(1fr pad(8, #4), 120 grid))", see Layout.lean to build one,
+/// redone on resize and only moves what changed, "" turns it off
+#[lean_effect]
+pub fn set_layout(interp: &mut Interpreter, spec: String) {
+    if spec.trim().is_empty() {
+        interp.effects.queue.push(Effect::SetLayout(None));
+        return;
+    }
+    match layout::Node::parse(&spec) {
+        Ok(node) => interp.effects.queue.push(Effect::SetLayout(Some(node))),
+        Err(e) => interp.report_error("set_layout", e),
+    }
+}
+
+#[lean_effect]
+pub fn cancel_timer(interp: &mut Interpreter, timer: TimerId) {
+    interp.effects.queue.push(Effect::CancelTimer { timer });
+}
+
+/// one Event.tick on the next frame, ask again from it to keep them coming
+#[lean_effect]
+pub fn request_frame(interp: &mut Interpreter) {
+    interp.effects.queue.push(Effect::RequestFrame);
+}
+
+#[lean_effect]
+pub fn quit(interp: &mut Interpreter) {
+    interp.effects.queue.push(Effect::Quit);
 }
@@ -0,0 +1,524 @@
+use super::audit;
+use super::{
+    lean_alloc_object, lean_dec_ref, lean_inc_ref, mk_ctor, mk_ctor_with_scalars, mk_lean_string,
+    LeanArray, LeanBoxedFloat, LeanBoxedU64, LeanExternalObject, LeanObject, LeanString,
+};
+use memoffset::raw_field;
+use std::marker::PhantomData;
+use std::{ffi, fmt, mem, slice, str};
+
+const LEAN_MAX_CTOR_TAG: u8 = 244;
+const LEAN_ARRAY_TAG: u8 = 246;
+const LEAN_STRING_TAG: u8 = 249;
+const LEAN_EXTERNAL_TAG: u8 = 254;
+
+// lean_box'd values (small nats, fieldless constructors, Unit) aren't pointers and aren't refcounted.
+pub fn is_scalar(o: *mut LeanObject) -> bool {
+    (o as usize) & 1 == 1
+}
+
+// What a LeanRef points at. check is run whenever a raw pointer is turned into a LeanRef.
+pub trait LeanType {
+    fn check(o: *mut LeanObject) -> Result<(), String>;
+}
+
+// Anything at all, for when the caller checks later with downcast.
+pub enum Any {}
+// A constructor of an inductive or structure, or a fieldless one boxed as a scalar.
+pub enum Ctor {}
+pub enum External {}
+
+fn tag(o: *mut LeanObject) -> Result<u8, String> {
+    if o.is_null() {
+        Err(String::from("null lean object"))
+    } else if is_scalar(o) {
+        Err(format!("unexpected scalar {}", o as usize >> 1))
+    } else {
+        Ok(unsafe { (*o).m_tag })
+    }
+}
+
+fn expect_tag(o: *mut LeanObject, expected: u8, what: &str) -> Result<(), String> {
+    match tag(o)? {
+        t if t == expected => Ok(()),
+        t => Err(format!("expected {} but found tag {}", what, t)),
+    }
+}
+
+// Float and UInt64 are both boxed as a constructor with no object fields and 8 bytes of scalars.
+fn expect_boxed_scalar(o: *mut LeanObject, what: &str) -> Result<(), String> {
+    expect_tag(o, 0, what)?;
+    match unsafe { (*o).m_other } {
+        0 => Ok(()),
+        n => Err(format!(
+            "expected boxed {} but found {} object fields",
+            what, n
+        )),
+    }
+}
+
+impl LeanType for Any {
+    fn check(o: *mut LeanObject) -> Result<(), String> {
+        if o.is_null() {
+            Err(String::from("null lean object"))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl LeanType for Ctor {
+    fn check(o: *mut LeanObject) -> Result<(), String> {
+        if is_scalar(o) {
+            return Ok(());
+        }
+        match tag(o)? {
+            t if t <= LEAN_MAX_CTOR_TAG => Ok(()),
+            t => Err(format!("expected a constructor but found tag {}", t)),
+        }
+    }
+}
+
+impl LeanType for External {
+    fn check(o: *mut LeanObject) -> Result<(), String> {
+        expect_tag(o, LEAN_EXTERNAL_TAG, "an external object")
+    }
+}
+
+impl LeanType for String {
+    fn check(o: *mut LeanObject) -> Result<(), String> {
+        expect_tag(o, LEAN_STRING_TAG, "a String")
+    }
+}
+
+impl LeanType for f64 {
+    fn check(o: *mut LeanObject) -> Result<(), String> {
+        expect_boxed_scalar(o, "Float")
+    }
+}
+
+impl LeanType for u64 {
+    fn check(o: *mut LeanObject) -> Result<(), String> {
+        expect_boxed_scalar(o, "UInt64")
+    }
+}
+
+// Elements are checked as they're read, not all up front.
+impl<T: LeanType> LeanType for Vec<T> {
+    fn check(o: *mut LeanObject) -> Result<(), String> {
+        expect_tag(o, LEAN_ARRAY_TAG, "an Array")
+    }
+}
+
+// An owned reference: Clone is lean_inc, Drop is lean_dec. Lean's `lean_obj_arg` parameters
+// and `lean_obj_res` results are owned, so they're taken with from_owned and handed back
+// with into_raw.
+pub struct LeanRef<T: LeanType> {
+    ptr: *mut LeanObject,
+    _type: PhantomData<T>,
+}
+
+// A `@&` (b_lean_obj_arg) parameter or a field read out of another object: valid while the
+// owner is, and never dec'd.
+pub struct LeanBorrowed<'a, T: LeanType> {
+    ptr: *mut LeanObject,
+    _type: PhantomData<&'a T>,
+}
+
+impl<T: LeanType> LeanRef<T> {
+    // On a failed check the reference is still released, the caller owned it after all.
+    #[track_caller]
+    pub unsafe fn from_owned(ptr: *mut LeanObject) -> Result<LeanRef<T>, String> {
+        let r = LeanRef::adopt(ptr);
+        match T::check(ptr) {
+            Ok(()) => Ok(r),
+            Err(e) => {
+                if ptr.is_null() {
+                    mem::forget(r);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    pub fn as_ptr(&self) -> *mut LeanObject {
+        self.ptr
+    }
+
+    // Gives our reference away, e.g. to Lean as an owned argument or result.
+    pub fn into_raw(self) -> *mut LeanObject {
+        let ptr = self.ptr;
+        audit::release(ptr);
+        mem::forget(self);
+        ptr
+    }
+
+    pub fn into_any(self) -> LeanRef<Any> {
+        self.retag()
+    }
+
+    // Every LeanRef is made here, so debug builds can count the references Rust holds.
+    #[track_caller]
+    fn adopt(ptr: *mut LeanObject) -> LeanRef<T> {
+        audit::acquire(ptr);
+        LeanRef {
+            ptr,
+            _type: PhantomData,
+        }
+    }
+
+    // The same reference as another type.
+    fn retag<U: LeanType>(self) -> LeanRef<U> {
+        let ptr = self.ptr;
+        mem::forget(self);
+        LeanRef {
+            ptr,
+            _type: PhantomData,
+        }
+    }
+}
+
+impl LeanRef<Any> {
+    pub fn downcast<U: LeanType>(self) -> Result<LeanRef<U>, String> {
+        U::check(self.ptr)?;
+        Ok(self.retag())
+    }
+}
+
+impl<T: LeanType> fmt::Debug for LeanRef<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "LeanRef({:?})", self.ptr)
+    }
+}
+
+impl<T: LeanType> Clone for LeanRef<T> {
+    #[track_caller]
+    fn clone(&self) -> LeanRef<T> {
+        if !is_scalar(self.ptr) {
+            lean_inc_ref(self.ptr);
+        }
+        LeanRef::adopt(self.ptr)
+    }
+}
+
+impl<T: LeanType> Drop for LeanRef<T> {
+    fn drop(&mut self) {
+        audit::release(self.ptr);
+        if !is_scalar(self.ptr) {
+            lean_dec_ref(self.ptr);
+        }
+    }
+}
+
+impl<'a, T: LeanType> LeanBorrowed<'a, T> {
+    pub unsafe fn new(ptr: *mut LeanObject) -> Result<LeanBorrowed<'a, T>, String> {
+        T::check(ptr)?;
+        Ok(LeanBorrowed {
+            ptr,
+            _type: PhantomData,
+        })
+    }
+
+    #[track_caller]
+    pub fn to_owned(&self) -> LeanRef<T> {
+        if !is_scalar(self.ptr) {
+            lean_inc_ref(self.ptr);
+        }
+        LeanRef::adopt(self.ptr)
+    }
+}
+
+impl LeanRef<String> {
+    #[track_caller]
+    pub fn new(string: &str) -> LeanRef<String> {
+        LeanRef::adopt(mk_lean_string(string) as *mut LeanObject)
+    }
+
+    // Unlike str_from_lean this can't outlive the reference keeping the string alive.
+    pub fn as_str(&self) -> &str {
+        let ls = self.ptr as *mut LeanString;
+        let ptr = raw_field!(ls, LeanString, m_data) as *const u8;
+        unsafe {
+            let bytes = slice::from_raw_parts(ptr, (*ls).m_size);
+            str::from_utf8_unchecked(ffi::CStr::from_bytes_with_nul_unchecked(bytes).to_bytes())
+        }
+    }
+}
+
+// lean_box_float / lean_box_uint64: a constructor with no object fields, so m_other is 0.
+fn alloc_boxed_scalar() -> *mut LeanObject {
+    let sz = mem::size_of::<LeanBoxedU64>();
+    unsafe {
+        let m = lean_alloc_object(sz) as *mut LeanObject;
+        (*m).m_rc = 1;
+        (*m).m_tag = 0;
+        (*m).m_other = 0;
+        (*m).m_cs_sz = sz as u16;
+        m
+    }
+}
+
+impl LeanRef<f64> {
+    #[track_caller]
+    pub fn new(val: f64) -> LeanRef<f64> {
+        let m = alloc_boxed_scalar();
+        unsafe { (*(m as *mut LeanBoxedFloat)).m_obj = val }
+        LeanRef::adopt(m)
+    }
+
+    pub fn get(&self) -> f64 {
+        unsafe { (*(self.ptr as *mut LeanBoxedFloat)).m_obj }
+    }
+}
+
+impl LeanRef<u64> {
+    #[track_caller]
+    pub fn new(val: u64) -> LeanRef<u64> {
+        let m = alloc_boxed_scalar();
+        unsafe { (*(m as *mut LeanBoxedU64)).m_obj = val }
+        LeanRef::adopt(m)
+    }
+
+    pub fn get(&self) -> u64 {
+        unsafe { (*(self.ptr as *mut LeanBoxedU64)).m_obj }
+    }
+}
+
+impl<T: LeanType> LeanRef<Vec<T>> {
+    pub fn len(&self) -> usize {
+        unsafe { (*(self.ptr as *mut LeanArray)).m_size }
+    }
+
+    pub fn at(&self, i: usize) -> Result<LeanBorrowed<'_, T>, String> {
+        if i >= self.len() {
+            return Err(format!(
+                "index {} out of bounds for array of {}",
+                i,
+                self.len()
+            ));
+        }
+        let a = self.ptr as *mut LeanArray;
+        unsafe {
+            let data = raw_field!(a, LeanArray, m_data) as *mut *mut LeanObject;
+            LeanBorrowed::new(*data.add(i))
+        }
+    }
+}
+
+// A scalar constructor field: Bool and fieldless enums are U8, Char is U32. Lean stores them
+// after the object fields, largest first and in declaration order within a size.
+#[derive(Debug, Clone, Copy)]
+pub enum Scalar {
+    U8(u8),
+    U32(u32),
+    U64(u64),
+    F64(f64),
+}
+
+impl Scalar {
+    fn size(&self) -> usize {
+        match self {
+            Scalar::U8(_) => 1,
+            Scalar::U32(_) => 4,
+            Scalar::U64(_) | Scalar::F64(_) => 8,
+        }
+    }
+
+    fn bytes(&self) -> Vec<u8> {
+        match self {
+            Scalar::U8(v) => v.to_ne_bytes().to_vec(),
+            Scalar::U32(v) => v.to_ne_bytes().to_vec(),
+            Scalar::U64(v) => v.to_ne_bytes().to_vec(),
+            Scalar::F64(v) => v.to_ne_bytes().to_vec(),
+        }
+    }
+}
+
+impl LeanRef<Ctor> {
+    // Object fields only; scalar fields go through the *_scalar accessors.
+    #[track_caller]
+    pub fn new(tag: u8, objs: Vec<LeanRef<Any>>) -> LeanRef<Ctor> {
+        LeanRef::<Ctor>::with_scalars(tag, objs, &[])
+    }
+
+    // Fields in declaration order, objects and scalars separately; the scalars are laid out here.
+    #[track_caller]
+    pub fn with_scalars(tag: u8, objs: Vec<LeanRef<Any>>, scalars: &[Scalar]) -> LeanRef<Ctor> {
+        let raw: Vec<*mut LeanObject> = objs.into_iter().map(LeanRef::into_raw).collect();
+        let mut sorted = scalars.to_vec();
+        sorted.sort_by_key(|s| std::cmp::Reverse(s.size()));
+        let bytes: Vec<u8> = sorted.iter().flat_map(Scalar::bytes).collect();
+        LeanRef::adopt(mk_ctor_with_scalars(tag, &raw, &bytes))
+    }
+
+    // A constructor without fields is lean_box(tag), unless every constructor is fieldless, in
+    // which case the whole type is passed around as an unboxed u8 instead.
+    pub fn nullary(tag: u8) -> LeanRef<Ctor> {
+        LeanRef::adopt((((tag as usize) << 1) | 1) as *mut LeanObject)
+    }
+
+    pub fn tag(&self) -> u8 {
+        if is_scalar(self.ptr) {
+            (self.ptr as usize >> 1) as u8
+        } else {
+            unsafe { (*self.ptr).m_tag }
+        }
+    }
+
+    pub fn num_objs(&self) -> usize {
+        if is_scalar(self.ptr) {
+            0
+        } else {
+            unsafe { (*self.ptr).m_other as usize }
+        }
+    }
+
+    pub fn field(&self, i: usize) -> Result<LeanBorrowed<'_, Any>, String> {
+        if i >= self.num_objs() {
+            return Err(format!(
+                "field {} out of bounds for constructor with {}",
+                i,
+                self.num_objs()
+            ));
+        }
+        unsafe { LeanBorrowed::new(*self.fields().add(i)) }
+    }
+
+    unsafe fn fields(&self) -> *mut *mut LeanObject {
+        (self.ptr as *mut u8).add(mem::size_of::<LeanObject>()) as *mut *mut LeanObject
+    }
+}
+
+impl LeanRef<External> {
+    pub fn data<T>(&self) -> *mut T {
+        unsafe { (*(self.ptr as *mut LeanExternalObject)).m_data as *mut T }
+    }
+}
+
+// Converts an owned effect argument into the Rust type the effect function takes. The reference
+// has already passed Lean's check, only what's inside it can still be wrong.
+pub trait FromLean: Sized {
+    type Lean: LeanType;
+    fn from_lean(r: LeanRef<Self::Lean>) -> Result<Self, String>;
+}
+
+impl FromLean for f64 {
+    type Lean = f64;
+    fn from_lean(r: LeanRef<f64>) -> Result<f64, String> {
+        Ok(r.get())
+    }
+}
+
+impl FromLean for f32 {
+    type Lean = f64;
+    fn from_lean(r: LeanRef<f64>) -> Result<f32, String> {
+        Ok(r.get() as f32)
+    }
+}
+
+impl FromLean for u64 {
+    type Lean = u64;
+    fn from_lean(r: LeanRef<u64>) -> Result<u64, String> {
+        Ok(r.get())
+    }
+}
+
+impl FromLean for usize {
+    type Lean = u64;
+    fn from_lean(r: LeanRef<u64>) -> Result<usize, String> {
+        Ok(r.get() as usize)
+    }
+}
+
+impl FromLean for String {
+    type Lean = String;
+    fn from_lean(r: LeanRef<String>) -> Result<String, String> {
+        Ok(r.as_str().to_owned())
+    }
+}
+
+// An Array, its elements checked one by one as they're read.
+impl<T: FromLean> FromLean for Vec<T> {
+    type Lean = Vec<T::Lean>;
+    fn from_lean(r: LeanRef<Vec<T::Lean>>) -> Result<Vec<T>, String> {
+        (0..r.len())
+            .map(|i| {
+                r.at(i)
+                    .and_then(|item| T::from_lean(item.to_owned()))
+                    .map_err(|e| format!("element {}: {}", i, e))
+            })
+            .collect()
+    }
+}
+
+// For effects that keep hold of the object itself.
+impl<T: LeanType> FromLean for LeanRef<T> {
+    type Lean = T;
+    fn from_lean(r: LeanRef<T>) -> Result<LeanRef<T>, String> {
+        Ok(r)
+    }
+}
+
+// The value an effect function returns, boxed the way Lean expects inside an IO result.
+pub trait IntoLean {
+    fn into_lean(self) -> LeanRef<Any>;
+}
+
+impl IntoLean for () {
+    fn into_lean(self) -> LeanRef<Any> {
+        // lean_box(0), which is how Unit is represented
+        LeanRef::adopt(super::LEAN_UNIT as *mut LeanObject)
+    }
+}
+
+impl IntoLean for u64 {
+    fn into_lean(self) -> LeanRef<Any> {
+        LeanRef::<u64>::new(self).into_any()
+    }
+}
+
+impl IntoLean for f64 {
+    fn into_lean(self) -> LeanRef<Any> {
+        LeanRef::<f64>::new(self).into_any()
+    }
+}
+
+impl IntoLean for String {
+    fn into_lean(self) -> LeanRef<Any> {
+        LeanRef::<String>::new(&self).into_any()
+    }
+}
+
+impl<T: LeanType> IntoLean for LeanRef<T> {
+    fn into_lean(self) -> LeanRef<Any> {
+        self.into_any()
+    }
+}
+
+// EStateM.Result.ok value world
+pub fn io_result_ok(value: impl IntoLean) -> *mut LeanObject {
+    let world = super::LEAN_UNIT as *mut LeanObject;
+    mk_ctor(0, &[value.into_lean().into_raw(), world])
+}
+
+// EStateM.Result.error (IO.userError msg) world
+pub fn io_result_error(msg: &str) -> *mut LeanObject {
+    let world = super::LEAN_UNIT as *mut LeanObject;
+    let err = unsafe { super::lean_mk_io_user_error(LeanRef::<String>::new(msg).into_raw()) };
+    mk_ctor(1, &[err, world])
+}
+
+// Takes an IO result returned from Lean, giving back the value or the IO.Error as text.
+#[track_caller]
+pub unsafe fn take_io_result(res: *mut LeanObject) -> Result<LeanRef<Any>, String> {
+    let res = LeanRef::<Ctor>::from_owned(res)?;
+    let value = res.field(0)?.to_owned();
+    match res.tag() {
+        0 => Ok(value),
+        _ => {
+            let msg =
+                LeanRef::<String>::from_owned(super::lean_io_error_to_string(value.into_raw()))?;
+            Err(msg.as_str().to_owned())
+        }
+    }
+}
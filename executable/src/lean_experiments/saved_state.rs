@@ -0,0 +1,65 @@
+use super::gui_api::Interpreter;
+use super::lean_ref::{Any, LeanRef};
+use super::structural::Structural;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+// The app state between launches, in whatever text the script's lean_save_state makes of it.
+#[derive(Debug)]
+pub struct SavedState {
+    pub path: PathBuf,
+    written: Option<String>, // what the file holds, to skip rewriting it
+    last_save: Instant,
+}
+
+impl SavedState {
+    pub fn new(path: PathBuf) -> SavedState {
+        SavedState {
+            path,
+            written: None,
+            last_save: Instant::now(),
+        }
+    }
+
+    // None when there's nothing saved or the script can't load it, and it starts from
+    // lean_on_init as usual.
+    pub fn restore(&mut self, lib: &Structural) -> Option<Result<LeanRef<Any>, String>> {
+        let saved = std::fs::read_to_string(&self.path).ok()?;
+        let state = lib.load_state(&saved)?;
+        if state.is_ok() {
+            println!("restored state from {:?}", self.path);
+            self.written = Some(saved);
+        }
+        Some(state)
+    }
+
+    pub fn save(&mut self, interp: &Interpreter) -> Result<(), String> {
+        self.last_save = Instant::now();
+        let saved = match &interp.lib {
+            Some(lib) => match lib.save_state(&interp.effects.app_state) {
+                Some(saved) => saved?,
+                None => return Ok(()),
+            },
+            None => return Ok(()),
+        };
+        if self.written.as_ref() == Some(&saved) {
+            return Ok(());
+        }
+        // written whole and renamed over, a crash mid-save keeps the last one
+        let tmp = self.path.with_extension("tmp");
+        std::fs::write(&tmp, &saved)
+            .and_then(|_| std::fs::rename(&tmp, &self.path))
+            .map_err(|e| format!("saving state to {:?}: {}", self.path, e))?;
+        self.written = Some(saved);
+        Ok(())
+    }
+
+    pub fn autosave(&mut self, interp: &Interpreter) -> Result<(), String> {
+        if self.last_save.elapsed() < AUTOSAVE_INTERVAL {
+            return Ok(());
+        }
+        self.save(interp)
+    }
+}
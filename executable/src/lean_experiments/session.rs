@@ -0,0 +1,142 @@
+use super::gui_api::{send_event_to_lean, ColID, Effect, Event, Interpreter};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::time::Instant;
+
+// One event and the effects the script made for it. A session log is these as JSON lines.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    // Lean's ids start after the columns the window makes itself, so it's logged to give replay
+    // the same fresh ids.
+    pub next_id: ColID,
+    pub event: Event,
+    pub effects: Vec<Effect>,
+}
+
+#[derive(Debug)]
+pub struct SessionLog {
+    path: String,
+    out: BufWriter<File>,
+}
+
+impl SessionLog {
+    pub fn create(path: &str) -> Result<SessionLog, String> {
+        let file = File::create(path).map_err(|e| format!("can't create {}: {}", path, e))?;
+        Ok(SessionLog {
+            path: path.to_owned(),
+            out: BufWriter::new(file),
+        })
+    }
+
+    // Flushed every entry, so a crash still leaves the log up to the event that caused it.
+    pub fn record(&mut self, entry: &Entry) -> Result<(), String> {
+        let line = serde_json::to_string(entry).map_err(|e| e.to_string())?;
+        writeln!(self.out, "{}", line)
+            .and_then(|_| self.out.flush())
+            .map_err(|e| format!("writing {}: {}", self.path, e))
+    }
+}
+
+pub fn read_log(path: &str) -> Result<Vec<Entry>, String> {
+    let file = File::open(path).map_err(|e| format!("can't open {}: {}", path, e))?;
+    BufReader::new(file)
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !matches!(line, Ok(l) if l.trim().is_empty()))
+        .map(|(i, line)| {
+            let line = line.map_err(|e| format!("reading {}: {}", path, e))?;
+            serde_json::from_str(&line).map_err(|e| format!("{}:{}: {}", path, i + 1, e))
+        })
+        .collect()
+}
+
+// Differences between what was logged and what the script makes now, one line each.
+fn diff_effects(logged: &[Effect], replayed: &[Effect]) -> Vec<String> {
+    (0..logged.len().max(replayed.len()))
+        .filter(|i| logged.get(*i) != replayed.get(*i))
+        .map(|i| {
+            format!(
+                "  effect {}: logged {:?}, replayed {:?}",
+                i,
+                logged.get(i),
+                replayed.get(i)
+            )
+        })
+        .collect()
+}
+
+// Sends every logged event to the loaded script, with no window, and compares the effects.
+// Returns how many events didn't match.
+pub fn replay(interp: &mut Interpreter, path: &str) -> Result<usize, String> {
+    let entries = read_log(path)?;
+    let mut mismatched = 0;
+    for (i, entry) in entries.iter().enumerate() {
+        interp.effects.next_id = entry.next_id;
+        let errors = interp.errors.len();
+        send_event_to_lean(interp, &entry.event);
+        // nothing performs them, so they only ever hold this event's
+        let effects = std::mem::take(&mut interp.effects.queue);
+        let diffs = diff_effects(&entry.effects, &effects);
+        if !diffs.is_empty() {
+            mismatched += 1;
+            println!(
+                "event {} {:?} differs:\n{}",
+                i,
+                entry.event,
+                diffs.join("\n")
+            );
+        }
+        for e in &interp.errors[errors..] {
+            println!("event {} {:?} threw: {}", i, entry.event, e);
+        }
+    }
+    println!(
+        "replayed {} events from {}, {} differed",
+        entries.len(),
+        path,
+        mismatched
+    );
+    Ok(mismatched)
+}
+
+fn events_per_sec(interp: &mut Interpreter, count: usize, remake_gui: bool) -> f64 {
+    let start = Instant::now();
+    for i in 0..count {
+        if remake_gui {
+            interp.gui = None;
+        }
+        send_event_to_lean(interp, &Event::Tick { time: i as f64 });
+        interp.effects.queue.clear();
+    }
+    count as f64 / start.elapsed().as_secs_f64()
+}
+
+// Times sending ticks to the loaded script with no window, against remaking the Gui for every
+// event the way it used to be. Ticks are about the cheapest event a script can handle, so it's
+// mostly the cost of the call itself.
+pub fn bench_events(interp: &mut Interpreter, count: usize) {
+    if interp.lib.is_none() {
+        println!("no libStructural loaded, nothing to time");
+        return;
+    }
+    let errors = interp.errors.len();
+    // once through first, so neither run pays for warming up
+    events_per_sec(interp, count.min(1000), false);
+    let remade = events_per_sec(interp, count, true);
+    let kept = events_per_sec(interp, count, false);
+    println!(
+        "{} events: {:.0}/s remaking the Gui each event, {:.0}/s keeping it ({:.2}x)",
+        count,
+        remade,
+        kept,
+        kept / remade
+    );
+    if interp.errors.len() > errors {
+        println!(
+            "{} events threw, the first: {}",
+            interp.errors.len() - errors,
+            interp.errors[errors]
+        );
+    }
+}
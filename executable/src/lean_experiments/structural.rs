@@ -0,0 +1,234 @@
+use super::gui_api::Interpreter;
+use super::lean_ref::{self, LeanRef};
+use super::{bindings, LeanObject, LEAN_UNIT};
+use libc::c_void;
+use std::ffi::{CStr, CString};
+use std::mem::transmute;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant, SystemTime};
+
+pub type InitializeFn = unsafe extern "C" fn(u8, libc::uintptr_t) -> *mut LeanObject;
+pub type OnInitFn = unsafe extern "C" fn(libc::uintptr_t) -> *mut LeanObject;
+pub type OnEventFn = unsafe extern "C" fn(
+    *mut LeanObject,
+    *mut LeanObject,
+    *mut LeanObject,
+    libc::uintptr_t,
+) -> *mut LeanObject;
+pub type GuiAbiFn = unsafe extern "C" fn(libc::uintptr_t) -> *mut LeanObject;
+pub type MigrateFn = unsafe extern "C" fn(*mut LeanObject, libc::uintptr_t) -> *mut LeanObject;
+pub type SaveStateFn = unsafe extern "C" fn(*mut LeanObject, libc::uintptr_t) -> *mut LeanObject;
+pub type LoadStateFn = unsafe extern "C" fn(*mut LeanObject, libc::uintptr_t) -> *mut LeanObject;
+
+// Where lake puts it, relative to executable/.
+pub fn default_path() -> PathBuf {
+    PathBuf::from(format!(
+        "../lean/.lake/build/lib/{}Structural{}",
+        std::env::consts::DLL_PREFIX,
+        std::env::consts::DLL_SUFFIX
+    ))
+}
+
+// Numbers the copies, dlopen would hand back the old handle for a path it already has open.
+static LOADS: AtomicUsize = AtomicUsize::new(0);
+
+// A dlopen'd libStructural and the entry points Rust calls. It's loaded from a copy, so lake
+// can overwrite the original while it's mapped.
+#[derive(Debug)]
+pub struct Structural {
+    handle: *mut c_void,
+    pub generation: usize,
+    pub initialize: InitializeFn,
+    pub on_init: OnInitFn,
+    pub on_event: OnEventFn,
+    pub gui_abi: GuiAbiFn,
+    pub migrate: Option<MigrateFn>, // lean_migrate_state, optional
+    pub save: Option<SaveStateFn>,  // lean_save_state, optional along with load
+    pub load: Option<LoadStateFn>,  // lean_load_state
+}
+
+fn dlerror() -> String {
+    unsafe {
+        let e = libc::dlerror();
+        if e.is_null() {
+            String::from("unknown dlopen error")
+        } else {
+            CStr::from_ptr(e).to_string_lossy().into_owned()
+        }
+    }
+}
+
+impl Structural {
+    // Copies, opens and looks up the library, without initializing it.
+    pub fn open(path: &Path) -> Result<Structural, String> {
+        let generation = LOADS.fetch_add(1, Ordering::Relaxed);
+        let copy = std::env::temp_dir().join(format!(
+            "{}Structural-{}-{}{}",
+            std::env::consts::DLL_PREFIX,
+            std::process::id(),
+            generation,
+            std::env::consts::DLL_SUFFIX
+        ));
+        std::fs::copy(path, &copy).map_err(|e| format!("can't copy {:?}: {}", path, e))?;
+        let name = CString::new(copy.to_string_lossy().as_bytes()).unwrap();
+        let handle = unsafe { libc::dlopen(name.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL) };
+        // the mapping outlives the file
+        let _ = std::fs::remove_file(&copy);
+        if handle.is_null() {
+            return Err(format!("can't load {:?}: {}", path, dlerror()));
+        }
+        let lookup = |symbol: &str| {
+            let name = CString::new(symbol).unwrap();
+            unsafe { libc::dlsym(handle, name.as_ptr()) }
+        };
+        let missing: Vec<&str> = bindings::REQUIRED_SYMBOLS
+            .iter()
+            .copied()
+            .filter(|s| lookup(s).is_null())
+            .collect();
+        if !missing.is_empty() {
+            return Err(format!(
+                "libStructural doesn't export {}, is it out of date?",
+                missing.join(", ")
+            ));
+        }
+        unsafe {
+            let optional = |symbol: &str| {
+                let f = lookup(symbol);
+                if f.is_null() {
+                    None
+                } else {
+                    Some(f)
+                }
+            };
+            Ok(Structural {
+                handle,
+                generation,
+                initialize: transmute::<*mut c_void, InitializeFn>(lookup("initialize_Structural")),
+                on_init: transmute::<*mut c_void, OnInitFn>(lookup("lean_on_init")),
+                on_event: transmute::<*mut c_void, OnEventFn>(lookup("lean_on_event")),
+                gui_abi: transmute::<*mut c_void, GuiAbiFn>(lookup("lean_gui_abi")),
+                migrate: optional("lean_migrate_state")
+                    .map(|f| transmute::<*mut c_void, MigrateFn>(f)),
+                save: optional("lean_save_state").map(|f| transmute::<*mut c_void, SaveStateFn>(f)),
+                load: optional("lean_load_state").map(|f| transmute::<*mut c_void, LoadStateFn>(f)),
+            })
+        }
+    }
+
+    // Any other export, for the experiments. T must be the extern "C" fn type it was built with.
+    pub unsafe fn get<T: Copy>(&self, symbol: &str) -> Result<T, String> {
+        assert_eq!(std::mem::size_of::<T>(), std::mem::size_of::<*mut c_void>());
+        let name = CString::new(symbol).unwrap();
+        let f = libc::dlsym(self.handle, name.as_ptr());
+        if f.is_null() {
+            return Err(format!("libStructural doesn't export {}", symbol));
+        }
+        Ok(std::mem::transmute_copy(&f))
+    }
+
+    // Runs the module initializer and checks it was built against our effects.
+    pub fn init(&self) -> Result<(), String> {
+        unsafe { lean_ref::take_io_result((self.initialize)(1, LEAN_UNIT))? };
+        bindings::check_structural(self)
+    }
+
+    pub fn initial_state(&self) -> Result<LeanRef<lean_ref::Any>, String> {
+        unsafe { lean_ref::take_io_result((self.on_init)(LEAN_UNIT)) }
+    }
+
+    // The script's own encoding of its state, None when it doesn't export lean_save_state.
+    pub fn save_state(&self, state: &LeanRef<lean_ref::Any>) -> Option<Result<String, String>> {
+        let save = self.save?;
+        Some(unsafe {
+            lean_ref::take_io_result(save(state.clone().into_raw(), LEAN_UNIT))
+                .and_then(|saved| saved.downcast::<String>())
+                .map(|saved| saved.as_str().to_owned())
+        })
+    }
+
+    pub fn load_state(&self, saved: &str) -> Option<Result<LeanRef<lean_ref::Any>, String>> {
+        let load = self.load?;
+        let saved = LeanRef::<String>::new(saved);
+        Some(unsafe { lean_ref::take_io_result(load(saved.into_raw(), LEAN_UNIT)) })
+    }
+}
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+// Polls the library's modification time, lake rewrites it on every rebuild.
+#[derive(Debug)]
+pub struct Watcher {
+    pub path: PathBuf,
+    seen: Option<SystemTime>,
+    changed: Option<SystemTime>,
+    last_poll: Instant,
+}
+
+impl Watcher {
+    pub fn new(path: PathBuf) -> Watcher {
+        let seen = modified(&path);
+        Watcher {
+            path,
+            seen,
+            changed: None,
+            last_poll: Instant::now(),
+        }
+    }
+
+    // True once a new version has stopped changing between two polls, so a half written
+    // library isn't loaded. Cheap to call every frame.
+    pub fn poll(&mut self) -> bool {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return false;
+        }
+        self.last_poll = Instant::now();
+        let now = modified(&self.path);
+        if now.is_none() || now == self.seen {
+            self.changed = None;
+            return false;
+        }
+        if self.changed != now {
+            self.changed = now;
+            return false;
+        }
+        self.seen = now;
+        self.changed = None;
+        true
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+// Swaps in a rebuilt library, carrying the app state over with its lean_migrate_state if it
+// has one, otherwise starting again from lean_on_init. On failure the old library keeps
+// running. Old libraries are never closed, objects made by their code may still be around.
+pub fn reload(interp: &mut Interpreter, path: &Path) -> Result<(), String> {
+    let lib = Structural::open(path)?;
+    lib.init()?;
+    let migrated = match (lib.migrate, &interp.lib) {
+        (Some(migrate), Some(_)) => {
+            // migrate takes its own reference, a throwing migration leaves ours usable
+            let old = interp.effects.app_state.clone();
+            match unsafe { lean_ref::take_io_result(migrate(old.into_raw(), LEAN_UNIT)) } {
+                Ok(state) => Some(state),
+                Err(e) => {
+                    interp.report_error("leanMigrateState", e);
+                    None
+                }
+            }
+        }
+        _ => None,
+    };
+    let state = match migrated {
+        Some(state) => state,
+        None => lib.initial_state()?,
+    };
+    interp.effects.app_state = state;
+    println!("reloaded {:?} (generation {})", path, lib.generation);
+    interp.lib = Some(lib);
+    Ok(())
+}
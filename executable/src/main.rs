@@ -1,6 +1,6 @@
 use cosmic_text::{
     Attrs, AttrsList, BufferLine, CacheKey, Color, FontSystem, LayoutGlyph, ShapeBuffer, Shaping,
-    SubpixelBin, SwashCache, Wrap,
+    SwashCache, Wrap,
 };
 use fontdb::Family;
 use miniquad::*;
@@ -9,16 +9,35 @@ use texture_packer::rect::Rect;
 use texture_packer::TexturePackerConfig;
 // use texture_packer::importer::
 // use image_importer::ImageImporter;
-use lean_experiments::gui_api::{send_event_to_lean, AppendMode, Interpreter};
+use aggregate::AggregateWorker;
+use animation::{Animated, Easing};
+use grid::{Align, GridColumn, GridRows, GridView};
+use layout::Layout;
+use lean_experiments::gui_api::{send_event_to_lean, Effect, Event, Interpreter, MouseKind};
+use lean_experiments::saved_state::SavedState;
+use lean_experiments::session;
+use lean_experiments::structural::{self, Watcher};
 use std::cmp::{max, min};
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::ops::Range;
+use std::path::PathBuf;
 use swash::scale::image::Content;
-
+use theme::{Role, Theme};
+use timers::Timers;
+
+mod aggregate;
+mod animation;
+mod export;
+mod glyph_check;
+mod grid;
+mod layout;
 mod lean_experiments;
 mod shader;
+mod theme;
+mod timers;
 
 #[repr(C)]
-#[derive(Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 struct Vec2 {
     x: f32,
     y: f32,
@@ -27,26 +46,43 @@ struct Vec2 {
 struct Vertex {
     pos: Vec2,
     uv: Vec2,
+    tint: f32, // 1 takes the color uniform's rgb, 0 keeps the texel's, for color emoji
 }
 
-struct Animating {
-    prev_pos: Vec2,
-    duration: f32,
-    start_time: f64,
+// The window in logical pixels, which is what columns, clips, Lean and events all use. Only
+// glyph rasterizing and scissor rects deal in physical ones.
+#[derive(Debug, Copy, Clone)]
+struct Screen {
+    width: f32,
+    height: f32,
+    dpi_scale: f32, // physical pixels per logical one
 }
 
-#[derive(Copy, Clone)]
+impl Screen {
+    // To the nearest physical pixel, so glyphs land on the subpixel bins they were rasterized at
+    // and their texels map one to one.
+    fn snap(&self, logical: f32) -> f32 {
+        (logical * self.dpi_scale).round() / self.dpi_scale
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
 struct Clip {
     pos: Vec2,
     size: Vec2,
 }
 
+// pos, clip and opacity are where the column is headed, drawing asks them where it is at the
+// time.
 struct Column {
-    pos: Vec2,
-    animation: Option<Animating>,
-    clip: Option<Clip>,
+    pos: Animated<Vec2>,
+    clip: Animated<Option<Clip>>,
+    opacity: Animated<f32>,            // 0 transparent to 1 opaque
+    role: Role,                        // the theme color its text is drawn in
+    color: Animated<Option<[f32; 4]>>, // from animate_color, drawn in place of the role's
     offset: usize,
     length: usize,
+    grid: Option<GridColumn>,
 }
 
 struct Atlas {
@@ -66,17 +102,21 @@ struct TextData {
 
 struct TextComponent {
     texture_atlas: Option<Atlas>,
-    glyph_loc: HashMap<CacheKey, (Rect, i32, i32)>,
+    glyph_loc: HashMap<CacheKey, (Rect, i32, i32, bool)>, // bool: a color glyph
     font_system: FontSystem,
     swash_cache: SwashCache,
     shape_buffer: ShapeBuffer,
+    dpi_scale: f32, // glyphs are laid out and rasterized at this times FONT_SIZE
 }
 
 impl TextComponent {
     pub fn new() -> TextComponent {
+        TextComponent::with_fonts(FontSystem::new())
+    }
+
+    pub fn with_fonts(font_system: FontSystem) -> TextComponent {
         let texture_atlas = None;
-        let glyph_loc: HashMap<CacheKey, (Rect, i32, i32)> = HashMap::new();
-        let font_system = FontSystem::new();
+        let glyph_loc: HashMap<CacheKey, (Rect, i32, i32, bool)> = HashMap::new();
         let swash_cache = SwashCache::new();
         let shape_buffer = ShapeBuffer::default();
         TextComponent {
@@ -85,19 +125,81 @@ impl TextComponent {
             font_system,
             swash_cache,
             shape_buffer,
+            dpi_scale: 1.0,
+        }
+    }
+}
+
+// Flat quads in the color uniform, up to MAX_SOLIDS of them a frame, over one white texel.
+struct Solid {
+    bindings: Bindings,
+}
+
+const MAX_SOLIDS: usize = 64;
+
+impl Solid {
+    pub fn new(ctx: &mut Box<dyn RenderingBackend>) -> Solid {
+        let texture = ctx.new_texture_from_rgba8(1, 1, &[0xff; 4]);
+        let vertex_buffer = ctx.new_buffer(
+            BufferType::VertexBuffer,
+            BufferUsage::Stream,
+            BufferSource::empty::<Vertex>(MAX_SOLIDS * 4),
+        );
+        let indices: Vec<u16> = (0..MAX_SOLIDS as u16)
+            .flat_map(|quad| [0, 1, 2, 0, 2, 3].map(|i| quad * 4 + i))
+            .collect();
+        let index_buffer = ctx.new_buffer(
+            BufferType::IndexBuffer,
+            BufferUsage::Immutable,
+            BufferSource::slice(&indices),
+        );
+        Solid {
+            bindings: Bindings {
+                vertex_buffers: vec![vertex_buffer],
+                index_buffer,
+                images: vec![texture],
+            },
         }
     }
+
+    fn quad(&self, rect: Clip) -> [Vertex; 4] {
+        let uv = Vec2 { x: 0.5, y: 0.5 };
+        let (x, y, w, h) = (rect.pos.x, rect.pos.y, rect.size.x, rect.size.y);
+        [
+            Vertex {
+                pos: Vec2 { x, y },
+                uv,
+                tint: 1.0,
+            },
+            Vertex {
+                pos: Vec2 { x: x + w, y },
+                uv,
+                tint: 1.0,
+            },
+            Vertex {
+                pos: Vec2 { x: x + w, y: y + h },
+                uv,
+                tint: 1.0,
+            },
+            Vertex {
+                pos: Vec2 { x, y: y + h },
+                uv,
+                tint: 1.0,
+            },
+        ]
+    }
 }
 
 struct TextLine {
     bindings: Bindings,
     index_count: i32,
+    width: f32,
 }
 
 fn layout<T: Into<String>>(text: T, text_component: &mut TextComponent) -> BufferLine {
     let bl_attrs = Attrs::new().family(Family::Name("Menlo"));
     let mut buffer_line = BufferLine::new(text, AttrsList::new(bl_attrs), Shaping::Advanced);
-    let bl_font_size = 82.0;
+    let bl_font_size = FONT_SIZE * text_component.dpi_scale;
 
     buffer_line.layout_in_buffer(
         &mut text_component.shape_buffer,
@@ -116,6 +218,91 @@ fn glyphs(buffer_line: &BufferLine) -> impl Iterator<Item = &LayoutGlyph> {
         .flat_map(|lines| lines.iter().flat_map(|line| line.glyphs.iter()))
 }
 
+// In logical pixels, layout() works in physical ones.
+fn line_width(buffer_line: &BufferLine, dpi_scale: f32) -> f32 {
+    buffer_line
+        .layout_opt()
+        .iter()
+        .flat_map(|lines| lines.iter())
+        .fold(0.0, |w: f32, line| w.max(line.w))
+        / dpi_scale
+}
+
+// Where a glyph's image goes in physical pixels, relative to the line's origin on the baseline.
+// x, y are the glyph's whole pixel position from LayoutGlyph::physical, the fraction went into
+// its subpixel bin and so is already in the image, and left, top are swash's placement of it.
+fn glyph_quad(x: i32, y: i32, rect: Rect, left: i32, top: i32) -> Clip {
+    Clip {
+        pos: Vec2 {
+            x: (x + left) as f32,
+            y: (y - top) as f32,
+        },
+        size: Vec2 {
+            x: rect.w as f32,
+            y: rect.h as f32,
+        },
+    }
+}
+
+// A quad per glyph, from the glyph's texels in the atlas to where it goes in logical pixels
+// relative to the line's origin on the baseline. Every glyph has to be in the atlas already.
+fn line_quads(
+    buffer_line: &BufferLine,
+    text_component: &TextComponent,
+    atlas_w: f32,
+    atlas_h: f32,
+) -> (Vec<Vertex>, Vec<u16>) {
+    let mut vertices: Vec<Vertex> = Vec::new();
+    let mut indices: Vec<u16> = Vec::new();
+
+    for glyph in glyphs(buffer_line) {
+        let physical = glyph.physical((0.0, 0.0), 1.0);
+        if let Some(&(rect, left, top, colored)) = text_component.glyph_loc.get(&physical.cache_key)
+        {
+            let pre_length = vertices.len() as u16;
+            let quad = glyph_quad(physical.x, physical.y, rect, left, top);
+            // the atlas is physical pixels, the quads logical
+            let dpi = text_component.dpi_scale;
+            let (x0, y0) = (quad.pos.x / dpi, quad.pos.y / dpi);
+            let (x1, y1) = (x0 + quad.size.x / dpi, y0 + quad.size.y / dpi);
+            // one texel per physical pixel, edge to edge
+            let (u0, v0) = (rect.x as f32 / atlas_w, rect.y as f32 / atlas_h);
+            let (u1, v1) = (
+                (rect.x + rect.w) as f32 / atlas_w,
+                (rect.y + rect.h) as f32 / atlas_h,
+            );
+            let tint = if colored { 0.0 } else { 1.0 };
+            vertices.push(Vertex {
+                pos: Vec2 { x: x0, y: y0 },
+                uv: Vec2 { x: u0, y: v0 },
+                tint,
+            });
+            vertices.push(Vertex {
+                pos: Vec2 { x: x1, y: y0 },
+                uv: Vec2 { x: u1, y: v0 },
+                tint,
+            });
+            vertices.push(Vertex {
+                pos: Vec2 { x: x1, y: y1 },
+                uv: Vec2 { x: u1, y: v1 },
+                tint,
+            });
+            vertices.push(Vertex {
+                pos: Vec2 { x: x0, y: y1 },
+                uv: Vec2 { x: u0, y: v1 },
+                tint,
+            });
+
+            [0, 1, 2, 0, 2, 3].map(|i| indices.push(pre_length + i));
+        } else {
+            // Can maybe tupule the atlas info with the glyphs
+            panic!("atlas does not have expected glyph");
+        }
+    }
+
+    (vertices, indices)
+}
+
 impl TextLine {
     pub fn new(
         atlas_id: TextureId,
@@ -125,67 +312,7 @@ impl TextLine {
         ctx: &mut Box<dyn RenderingBackend>,
         text_component: &mut TextComponent,
     ) -> TextLine {
-        // showing the text using the atlas:
-        let mut vertices: Vec<Vertex> = Vec::new();
-        let mut indices: Vec<u16> = Vec::new();
-
-        for glyph in glyphs(buffer_line) {
-            // todo abstract
-            let real_key = glyph.physical((0.0, 0.0), 1.0).cache_key;
-            let glyph_key = CacheKey {
-                x_bin: SubpixelBin::Zero,
-                y_bin: SubpixelBin::Zero,
-                ..real_key
-            };
-            // This is using the atlas for width, but if I scale it that won't always be true.
-            // just because there's no "height" for glyphs and I'm not sure why.
-            if let Some((rect, left, top)) = text_component.glyph_loc.get(&glyph_key) {
-                let pre_length = vertices.len() as u16;
-                // just taking stabs in the dark. This is clearly not right.
-                //  - (real_key.x_bin.as_float() * -1.0)
-                let vx = glyph.x + (*left as f32); //glyph.physical((0.0, 0.0), 1.0).x as f32;
-                let vy = (glyph.y as f32) + (rect.h as f32) - (*top as f32); // glyph.physical((0.0, 0.0), 1.0).y as f32;
-                                                                             // Not sure why I need to add 2.5 width, the eg underscores don't line up quite right w/o it.
-                                                                             // could be drawing the textures a little off and this is just compensating.
-                                                                             // Also it's definitely not right, eg it will double width of a narrow character etc
-                                                                             // 1.045 leaves tiny gaps in underscores at pt 82
-                let vw = rect.w as f32 * 1.05; // glyph.w; //using rect.w makes the characters look right but spaced wrong.d
-                let vh = rect.h as f32 * 1.05;
-                let tx = (rect.x as f32 + 0.5) / atlas_w;
-                let ty = (rect.y as f32 + 0.5) / atlas_h;
-                let tw = (rect.w as f32 - 1.0 + 0.5) / atlas_w;
-                let th = (rect.h as f32 - 1.0 + 0.5) / atlas_h;
-                vertices.push(Vertex {
-                    pos: Vec2 { x: vx, y: vy - vh },
-                    uv: Vec2 { x: tx, y: ty },
-                });
-                vertices.push(Vertex {
-                    pos: Vec2 {
-                        x: vx + vw,
-                        y: vy - vh,
-                    },
-                    uv: Vec2 { x: tx + tw, y: ty },
-                });
-                vertices.push(Vertex {
-                    pos: Vec2 { x: vx + vw, y: vy },
-                    uv: Vec2 {
-                        x: tx + tw,
-                        y: ty + th,
-                    },
-                });
-                vertices.push(Vertex {
-                    pos: Vec2 { x: vx, y: vy },
-                    uv: Vec2 { x: tx, y: ty + th },
-                });
-
-                [0, 1, 2, 0, 2, 3].map(|i| indices.push(pre_length + i));
-                // println!("Adding quad: {:?}", (vx, vy, vw, vh, tx, real_key.x_bin.as_float(), ty, tw, th));
-                // println!("adding quad: {:?}", glyph);
-            } else {
-                // Can maybe tupule the atlas info with the glyphs
-                panic!("atlas does not have expected glyph");
-            }
-        }
+        let (vertices, indices) = line_quads(buffer_line, text_component, atlas_w, atlas_h);
 
         let vertex_buffer = ctx.new_buffer(
             BufferType::VertexBuffer,
@@ -210,141 +337,171 @@ impl TextLine {
         TextLine {
             bindings,
             index_count,
+            width: line_width(buffer_line, text_component.dpi_scale),
         }
     }
 }
 
-impl Stage {
-    pub fn invalidate_atlas(&mut self) {
-        self.text_component.glyph_loc.clear();
-        if let Some(texture) = &self.text_component.texture_atlas {
-            self.ctx.delete_texture(texture.id)
-        }
-        self.text_component.texture_atlas = None;
-    }
-    pub fn regenerate_atlas(&mut self) {
-        println!("Regenerating atlas");
-        let config = TexturePackerConfig {
-            max_width: ATLAS_WIDTH,
-            max_height: 40000,
-            allow_rotation: false,
-            texture_outlines: true,
-            border_padding: 2,
-            ..Default::default()
-        };
-        let mut packer = SkylinePacker::new(config);
-
-        self.invalidate_atlas();
+// Packs the glyphs the lines use into glyph_loc, one per subpixel bin, and rasterizes them
+// into an rgba atlas ATLAS_WIDTH texels across, white where a mask covers. No GPU involved, the
+// texture is made from what this returns.
+fn pack_atlas(text_component: &mut TextComponent, lines: &[BufferLine]) -> Vec<u8> {
+    let config = TexturePackerConfig {
+        max_width: ATLAS_WIDTH,
+        max_height: 40000,
+        allow_rotation: false,
+        texture_outlines: true,
+        border_padding: 2,
+        ..Default::default()
+    };
+    let mut packer = SkylinePacker::new(config);
+
+    for glyph in lines.iter().flat_map(glyphs) {
+        // one per subpixel bin the glyph lands on
+        let glyph_key = glyph.physical((0.0, 0.0), 1.0).cache_key;
+        if let Some((_rect, _left, _top, _colored)) = text_component.glyph_loc.get(&glyph_key) {
+            /*
+            println!(
+                "cached: {:?}: {},{}: {}x{}",
+                glyph_key, rect.x, rect.y, rect.w, rect.h
+            );*/
+        } else {
+            let maybe_img = text_component
+                .swash_cache
+                .get_image(&mut text_component.font_system, glyph_key);
 
-        for glyph in self.text_data.laid_out_lines.iter().flat_map(glyphs) {
-            let glyph_key = CacheKey {
-                x_bin: SubpixelBin::Zero,
-                y_bin: SubpixelBin::Zero,
-                ..glyph.physical((0.0, 0.0), 1.0).cache_key
-            };
-            if let Some((_rect, _left, _top)) = self.text_component.glyph_loc.get(&glyph_key) {
-                /*
-                println!(
-                    "cached: {:?}: {},{}: {}x{}",
-                    glyph_key, rect.x, rect.y, rect.w, rect.h
-                );*/
-            } else {
-                let maybe_img = self
-                    .text_component
-                    .swash_cache
-                    .get_image(&mut self.text_component.font_system, glyph_key);
-
-                if let Some(img) = maybe_img {
-                    let width = img.placement.width;
-                    let height = img.placement.height;
-
-                    let name = "hi";
-                    let frame = packer.pack(name, &Rect::new(0, 0, width, height));
-                    if let Some(frm) = frame {
-                        self.text_component.glyph_loc.insert(
-                            glyph_key,
-                            (frm.frame, img.placement.left, img.placement.top),
-                        );
-                        /*
-                        println!(
-                            "new:    {:?}: {},{}: {}x{}",
-                            glyph_key, frm.frame.x, frm.frame.y, frm.frame.w, frm.frame.h
-                        );*/
-                    }
+            if let Some(img) = maybe_img {
+                let width = img.placement.width;
+                let height = img.placement.height;
+
+                let name = "hi";
+                let frame = packer.pack(name, &Rect::new(0, 0, width, height));
+                if let Some(frm) = frame {
+                    text_component.glyph_loc.insert(
+                        glyph_key,
+                        (
+                            frm.frame,
+                            img.placement.left,
+                            img.placement.top,
+                            img.content == Content::Color,
+                        ),
+                    );
+                    /*
+                    println!(
+                        "new:    {:?}: {},{}: {}x{}",
+                        glyph_key, frm.frame.x, frm.frame.y, frm.frame.w, frm.frame.h
+                    );*/
                 }
             }
         }
+    }
 
-        let atlas_height = packer
-            .skylines
-            .iter()
-            .fold(0, |h, skyline| max(h, skyline.y));
-        println!("max_height: {}", atlas_height);
-
-        let mut atlas_texture =
-            vec![0x88_u8; usize::try_from(ATLAS_WIDTH * atlas_height).unwrap() * 4];
-        for (glyph_key, (rect, _left, _top)) in &self.text_component.glyph_loc {
-            let maybe_img = self
-                .text_component
-                .swash_cache
-                .get_image(&mut self.text_component.font_system, *glyph_key);
-            if let Some(img) = maybe_img {
-                // println!["img: {:?}", img.placement];
-                let w = img.placement.width;
-                let h = img.placement.height;
-                let len = img.data.len();
-                match img.content {
-                    Content::Mask => {
-                        assert!(
-                            usize::try_from(w * h).unwrap() == len,
-                            "unexpected img size: {} x {} x {:?} vs {}",
-                            w,
-                            h,
-                            img.content,
-                            len
-                        );
-                        // println!("drawing {:?}", (rect, w, h));
-                        for y in 0..h {
-                            for x in 0..w {
+    let atlas_height = packer
+        .skylines
+        .iter()
+        .fold(0, |h, skyline| max(h, skyline.y));
+    println!("max_height: {}", atlas_height);
+
+    let mut atlas_texture = vec![0x88_u8; usize::try_from(ATLAS_WIDTH * atlas_height).unwrap() * 4];
+    for (glyph_key, (rect, _left, _top, _colored)) in &text_component.glyph_loc {
+        let maybe_img = text_component
+            .swash_cache
+            .get_image(&mut text_component.font_system, *glyph_key);
+        if let Some(img) = maybe_img {
+            // println!["img: {:?}", img.placement];
+            let w = img.placement.width;
+            let h = img.placement.height;
+            let len = img.data.len();
+            match img.content {
+                Content::Mask => {
+                    assert!(
+                        usize::try_from(w * h).unwrap() == len,
+                        "unexpected img size: {} x {} x {:?} vs {}",
+                        w,
+                        h,
+                        img.content,
+                        len
+                    );
+                    // println!("drawing {:?}", (rect, w, h));
+                    for y in 0..h {
+                        for x in 0..w {
+                            let target = usize::try_from(
+                                (rect.y + y) * ATLAS_WIDTH * 4 + ((rect.x + x) * 4),
+                            )
+                            .unwrap();
+                            // white, the shader colors it with the column's role
+                            atlas_texture[target + 0] = 0xff; // r
+                            atlas_texture[target + 1] = 0xff;
+                            atlas_texture[target + 2] = 0xff;
+                            // a
+                            atlas_texture[target + 3] =
+                                img.data[usize::try_from(y * w + x).unwrap()];
+                        }
+                    }
+                }
+                Content::Color => {
+                    assert!(
+                        usize::try_from(w * h * 4).unwrap() == len,
+                        "unexpected img size: {} x {} x {:?} vs {}",
+                        w,
+                        h,
+                        img.content,
+                        len
+                    );
+                    for y in 0..h {
+                        for x in 0..w {
+                            for c in 0..4 {
                                 let target = usize::try_from(
-                                    (rect.y + y) * ATLAS_WIDTH * 4 + ((rect.x + x) * 4),
+                                    (rect.y + y) * ATLAS_WIDTH * 4 + (rect.x + x) * 4 + c,
                                 )
                                 .unwrap();
-                                atlas_texture[target + 0] = TEXT_R; // r
-                                atlas_texture[target + 1] = TEXT_G;
-                                atlas_texture[target + 2] = TEXT_B;
-                                // a
-                                atlas_texture[target + 3] =
-                                    img.data[usize::try_from(y * w + x).unwrap()];
-                            }
-                        }
-                    }
-                    Content::Color => {
-                        assert!(
-                            usize::try_from(w * h * 4).unwrap() == len,
-                            "unexpected img size: {} x {} x {:?} vs {}",
-                            w,
-                            h,
-                            img.content,
-                            len
-                        );
-                        for y in 0..h {
-                            for x in 0..w {
-                                for c in 0..4 {
-                                    let target = usize::try_from(
-                                        (rect.y + y) * ATLAS_WIDTH * 4 + (rect.x + x) * 4 + c,
-                                    )
-                                    .unwrap();
-                                    let source = usize::try_from(y * w * 4 + x * 4 + c).unwrap();
-                                    atlas_texture[target] = img.data[source];
-                                }
+                                let source = usize::try_from(y * w * 4 + x * 4 + c).unwrap();
+                                atlas_texture[target] = img.data[source];
                             }
                         }
                     }
-                    x => println!("unknown content {:?}", x),
                 }
+                x => println!("unknown content {:?}", x),
             }
         }
+    }
+    atlas_texture
+}
+
+impl Stage {
+    pub fn invalidate_atlas(&mut self) {
+        self.text_component.glyph_loc.clear();
+        if let Some(texture) = &self.text_component.texture_atlas {
+            self.ctx.delete_texture(texture.id)
+        }
+        self.text_component.texture_atlas = None;
+    }
+
+    // Moving to a display with another scale re-lays out every line at the new one, and the
+    // next bind_text rasterizes them into a fresh atlas. Logical sizes stay the same.
+    pub fn update_dpi_scale(&mut self) {
+        let dpi_scale = window::dpi_scale();
+        if dpi_scale == self.text_component.dpi_scale {
+            return;
+        }
+        println!(
+            "dpi scale {} -> {}",
+            self.text_component.dpi_scale, dpi_scale
+        );
+        self.text_component.dpi_scale = dpi_scale;
+        self.screen.dpi_scale = dpi_scale;
+        for line in self.text_data.laid_out_lines.iter_mut() {
+            *line = layout(line.text().to_owned(), &mut self.text_component);
+        }
+        self.text_data.unbound_laid_out_offset = 0;
+        self.text_data.unbound_laid_out_length = self.text_data.laid_out_lines.len();
+        self.invalidate_atlas();
+    }
+
+    pub fn regenerate_atlas(&mut self) {
+        println!("Regenerating atlas");
+        self.invalidate_atlas();
+        let atlas_texture = pack_atlas(&mut self.text_component, &self.text_data.laid_out_lines);
 
         let a_height_u =
             u16::try_from(u32::try_from(atlas_texture.len()).unwrap() / (ATLAS_WIDTH * 4)).unwrap();
@@ -394,11 +551,7 @@ impl Stage {
             .iter()
             .flat_map(glyphs)
             .any(|glyph| {
-                let glyph_key = CacheKey {
-                    x_bin: SubpixelBin::Zero,
-                    y_bin: SubpixelBin::Zero,
-                    ..glyph.physical((0.0, 0.0), 1.0).cache_key
-                };
+                let glyph_key = glyph.physical((0.0, 0.0), 1.0).cache_key;
                 !self.text_component.glyph_loc.contains_key(&glyph_key)
             });
 
@@ -424,6 +577,10 @@ impl Stage {
                             )
                         }),
                 );
+                // a column that shrank leaves stale lines at the end
+                self.text_data
+                    .bound_lines
+                    .truncate(self.text_data.laid_out_lines.len());
             }
         };
         self.text_data.unbound_laid_out_offset = self.text_data.laid_out_lines.len();
@@ -445,11 +602,14 @@ fn insert_text(
     {
         let mut cur_offset = new_offset;
         text_data.columns.push(Column {
-            pos,
-            animation: None,
-            clip,
+            pos: Animated::new(pos),
+            clip: Animated::new(clip),
+            opacity: Animated::new(1.0),
+            role: Role::Text,
+            color: Animated::new(None),
             length: texts.len(),
             offset: cur_offset,
+            grid: None,
         });
         cur_offset += texts.len();
         new_size += texts.len();
@@ -470,41 +630,111 @@ fn replace_text(
     col_id: usize,
     texts: &'_ [String],
 ) {
-    let col = &text_data.columns[col_id];
-    assert!(col.length == texts.len());
+    let offset = text_data.columns[col_id].offset;
+    let old_length = text_data.columns[col_id].length;
     text_data.laid_out_lines.splice(
-        col.offset..col.offset + col.length,
+        offset..offset + old_length,
         texts.iter().map(|text| layout(text, text_component)),
     );
-    let unbound_offset = text_data.unbound_laid_out_offset.min(col.offset);
-    let unbound_length = max(
-        col.offset + col.length,
-        text_data.unbound_laid_out_offset + text_data.unbound_laid_out_length,
-    ) - unbound_offset;
+    let unbound_offset = text_data.unbound_laid_out_offset.min(offset);
+    let unbound_end = if texts.len() == old_length {
+        max(
+            offset + old_length,
+            text_data.unbound_laid_out_offset + text_data.unbound_laid_out_length,
+        )
+    } else {
+        // every later column shifts, so everything after this one has to be rebound
+        text_data.columns[col_id].length = texts.len();
+        for column in text_data.columns[col_id + 1..].iter_mut() {
+            column.offset = column.offset + texts.len() - old_length;
+        }
+        text_data.laid_out_lines.len()
+    };
+    text_data.unbound_laid_out_offset = unbound_offset;
+    text_data.unbound_laid_out_length = unbound_end - unbound_offset;
+}
+
+// Lays out lines first.. of a column again, as many as there were, so nothing after them moves
+// and only they are bound again.
+fn replace_lines(
+    text_data: &mut TextData,
+    text_component: &mut TextComponent,
+    col_id: usize,
+    first: usize,
+    texts: &'_ [String],
+) {
+    let offset = text_data.columns[col_id].offset + first;
+    let end = offset + texts.len();
+    for (line, text) in text_data.laid_out_lines[offset..end].iter_mut().zip(texts) {
+        *line = layout(text, text_component);
+    }
+    let (unbound_offset, unbound_end) = if text_data.unbound_laid_out_length == 0 {
+        (offset, end)
+    } else {
+        (
+            text_data.unbound_laid_out_offset.min(offset),
+            max(
+                end,
+                text_data.unbound_laid_out_offset + text_data.unbound_laid_out_length,
+            ),
+        )
+    };
     text_data.unbound_laid_out_offset = unbound_offset;
-    text_data.unbound_laid_out_length = unbound_length;
+    text_data.unbound_laid_out_length = unbound_end - unbound_offset;
+}
+
+// Drops every column from col_id on, and their lines.
+fn truncate_columns(text_data: &mut TextData, col_id: usize) {
+    let offset = match text_data.columns.get(col_id) {
+        Some(column) => column.offset,
+        None => return,
+    };
+    text_data.columns.truncate(col_id);
+    text_data.laid_out_lines.truncate(offset);
+    text_data.bound_lines.truncate(offset);
+    let unbound_end = min(
+        text_data.unbound_laid_out_offset + text_data.unbound_laid_out_length,
+        offset,
+    );
+    text_data.unbound_laid_out_offset = min(text_data.unbound_laid_out_offset, offset);
+    text_data.unbound_laid_out_length = unbound_end - text_data.unbound_laid_out_offset;
 }
 
 struct Stage {
     ctx: Box<dyn RenderingBackend>,
     pipeline: Pipeline,
-    window_width: f32,
-    window_height: f32,
+    screen: Screen,
     draws_remaining: i32,
-    interp: Interpreter,
+    interp: Box<Interpreter>,
     text_component: TextComponent,
     text_data: TextData,
+    aggregates: AggregateWorker,
+    grid_view: Option<GridView>,
+    rows: GridRows,
+    error_overlay: usize, // a column of main's, drawn last, see show_errors
+    errors_shown: usize,
+    watcher: Watcher,
+    builtin_columns: usize, // made by main before Lean's, kept on reload
+    saved_state: Option<SavedState>,
+    finished: bool, // saved and Lean shut down, by a quit or the drop
+    timers: Timers,
+    layout: Layout, // from setLayout, places its columns and the grid on every resize
+    dividers: Solid,
+    error_backdrop: Solid,
+    theme: Theme,
+    startup_theme: Theme, // from --theme, what a reload goes back to
 }
 
 // in texels I.e. not bit array u8 length.
 const ATLAS_WIDTH: u32 = 100;
-const BACKGROUND_COLOR: (f32, f32, f32, f32) = (1.0, 0.9, 0.9, 1.0);
-const TEXT_R: u8 = 0x10;
-const TEXT_G: u8 = 0x10;
-const TEXT_B: u8 = 0x30;
 
 impl Stage {
-    pub fn new(window_width: f32, window_height: f32, interp: Interpreter) -> Stage {
+    pub fn new(
+        interp: Box<Interpreter>,
+        watcher: Watcher,
+        saved_state: Option<SavedState>,
+        theme: Theme,
+    ) -> Stage {
         let mut ctx: Box<dyn RenderingBackend> = window::new_rendering_backend();
 
         let shader = ctx
@@ -548,104 +778,657 @@ impl Stage {
             &[
                 VertexAttribute::new("in_pos", VertexFormat::Float2),
                 VertexAttribute::new("in_uv", VertexFormat::Float2),
+                VertexAttribute::new("in_tint", VertexFormat::Float1),
             ],
             shader,
             params,
         );
 
         let draws_remaining = 600;
-
-        let text_component = TextComponent::new();
+        let dividers = Solid::new(&mut ctx);
+        let error_backdrop = Solid::new(&mut ctx);
+
+        let mut text_component = TextComponent::new();
+        let dpi_scale = window::dpi_scale();
+        text_component.dpi_scale = dpi_scale;
+        let (width, height) = window::screen_size();
+        let screen = Screen {
+            width: width / dpi_scale,
+            height: height / dpi_scale,
+            dpi_scale,
+        };
 
         let unbound_laid_out_offset = 0;
         let unbound_laid_out_length = 0;
         let laid_out_lines = Vec::new();
         let bound_lines = Vec::new();
         let columns = Vec::new();
-        let text_data = TextData {
+        let mut text_data = TextData {
             unbound_laid_out_offset,
             unbound_laid_out_length,
             laid_out_lines,
             bound_lines,
             columns,
         };
+        // the first column, before any of Lean's, empty until there's an error
+        let error_overlay = insert_text(
+            &mut text_data,
+            &mut text_component,
+            Vec2 {
+                x: 20.0 + ERROR_PADDING,
+                y: 20.0 + ERROR_PADDING + LINE_HEIGHT - LINE_DESCENT,
+            },
+            None,
+            &[],
+        );
+        text_data.columns[error_overlay].role = Role::Error;
 
         Stage {
             ctx,
             pipeline,
-            window_width,
-            window_height,
+            screen,
             draws_remaining,
             interp,
             text_component,
             text_data,
+            aggregates: AggregateWorker::spawn(),
+            grid_view: None,
+            rows: GridRows::default(),
+            error_overlay,
+            errors_shown: 0,
+            watcher,
+            builtin_columns: 0,
+            saved_state,
+            finished: false,
+            timers: Timers::default(),
+            layout: Layout::default(),
+            dividers,
+            error_backdrop,
+            theme: theme.clone(),
+            startup_theme: theme,
+        }
+    }
+
+    // The latest Lean errors in the overlay, faded in when it goes from none to some.
+    pub fn show_errors(&mut self) {
+        let errors = &self.interp.errors;
+        if errors.len() == self.errors_shown {
+            return;
+        }
+        self.errors_shown = errors.len();
+        let lines: Vec<String> = errors[errors.len().saturating_sub(ERRORS_SHOWN)..]
+            .iter()
+            .flat_map(|e| e.lines().map(String::from))
+            .collect();
+        let col_id = self.error_overlay;
+        if self.text_data.columns[col_id].length == 0 {
+            let opacity = &mut self.text_data.columns[col_id].opacity;
+            opacity.set(0.0);
+            opacity.animate(date::now(), 1.0, ERROR_FADE, Easing::EaseInOut);
+        }
+        replace_text(
+            &mut self.text_data,
+            &mut self.text_component,
+            col_id,
+            &lines,
+        );
+    }
+
+    // Swaps in a rebuilt libStructural and rebuilds everything the old one made from Init.
+    pub fn reload_lean(&mut self) {
+        let path = self.watcher.path.clone();
+        if let Err(e) = structural::reload(&mut self.interp, &path) {
+            self.interp.report_error("reload", e);
+            return;
+        }
+        self.interp.errors.clear();
+        self.errors_shown = 0;
+        replace_text(
+            &mut self.text_data,
+            &mut self.text_component,
+            self.error_overlay,
+            &[],
+        );
+        self.grid_view = None;
+        self.rows = GridRows::default();
+        self.layout = Layout::default();
+        self.theme = self.startup_theme.clone();
+        self.timers.clear();
+        // summaries still on their way are for the old columns
+        self.aggregates = AggregateWorker::spawn();
+        truncate_columns(&mut self.text_data, self.builtin_columns);
+        self.interp.effects.queue.clear();
+        self.interp.effects.next_id = self.builtin_columns as u64;
+        self.send_event(Event::Init);
+    }
+
+    // Moves and clips the layout's columns to their rects in the window, only the ones whose rect
+    // changed since the last time. Text sits on the first line's baseline, so it's dropped by that
+    // from the rect's top.
+    pub fn apply_layout(&mut self) {
+        let window = Clip {
+            pos: Vec2 { x: 0.0, y: 0.0 },
+            size: Vec2 {
+                x: self.screen.width,
+                y: self.screen.height,
+            },
+        };
+        let changed = self.layout.reflow(window, self.text_data.columns.len());
+        for (col, placement) in changed.columns {
+            let column = &mut self.text_data.columns[col as usize];
+            column.pos.set(Vec2 {
+                x: placement.rect.pos.x,
+                y: placement.rect.pos.y + LINE_HEIGHT - LINE_DESCENT,
+            });
+            column.clip.set(Some(placement.clip));
+        }
+        if let Some(rect) = changed.grid {
+            // a grid leaf without setGridView shows the grid with nothing frozen
+            let view = self.grid_view.get_or_insert_with(|| GridView {
+                pos: rect.pos,
+                size: rect.size,
+                frozen_rows: 0,
+                pinned_columns: 0,
+                scroll: Animated::new(Vec2 { x: 0.0, y: 0.0 }),
+            });
+            view.pos = rect.pos;
+            view.size = rect.size;
+            // re-clamps the scroll against the new size
+            self.scroll_grid(0.0, 0.0);
+        }
+    }
+
+    // Saves the state and shuts Lean down, the first time it's called.
+    pub fn shut_down(&mut self) {
+        if self.finished {
+            return;
+        }
+        self.finished = true;
+        if let Some(saved) = &mut self.saved_state {
+            if let Err(e) = saved.save(&self.interp) {
+                println!("not saved: {}", e);
+            }
+        }
+        self.interp.shutdown();
+    }
+
+    pub fn send_event(&mut self, event: Event) {
+        send_event_to_lean(&mut self.interp, &event);
+        perform_effects(self);
+    }
+
+    pub fn scroll_grid(&mut self, dx: f32, dy: f32) {
+        let view = match &self.grid_view {
+            Some(view) => view.clone(),
+            None => return,
+        };
+        let grid_columns = || {
+            self.text_data
+                .columns
+                .iter()
+                .filter(|column| column.grid.is_some())
+        };
+        let body_rows = grid_columns()
+            .map(|column| {
+                shown_lines(column, &self.rows)
+                    .len()
+                    .saturating_sub(view.frozen_rows)
+            })
+            .max()
+            .unwrap_or(0);
+        let footer_rows = grid_columns()
+            .map(|column| footer_range(column).len())
+            .max()
+            .unwrap_or(0);
+        let body_height = view.size.y - (view.frozen_rows + footer_rows) as f32 * LINE_HEIGHT;
+        let max_y = (body_rows as f32 * LINE_HEIGHT - body_height).max(0.0);
+        let content_right =
+            grid_columns()
+                .skip(view.pinned_columns)
+                .fold(0.0, |right: f32, column| {
+                    let width = column_width(&self.text_data, column);
+                    right.max(column.pos.target().x + width)
+                });
+        let max_x = (content_right - (view.pos.x + view.size.x)).max(0.0);
+        if let Some(view) = &mut self.grid_view {
+            let scroll = view.scroll.target();
+            let to = Vec2 {
+                x: (scroll.x + dx).clamp(0.0, max_x),
+                y: (scroll.y + dy).clamp(0.0, max_y),
+            };
+            if to != scroll {
+                // eased, so wheel steps glide, and a step arriving mid-glide carries on from it
+                view.scroll
+                    .animate(date::now(), to, SCROLL_GLIDE, Easing::CubicOut);
+            }
+        }
+    }
+
+    // The column's grid, making it a grid column if it wasn't one yet.
+    pub fn grid_column(&mut self, col_id: usize) -> &mut GridColumn {
+        self.text_data.columns[col_id]
+            .grid
+            .get_or_insert_with(|| GridColumn::new(format!("column {}", col_id)))
+    }
+
+    // Lays out the footer again after a new summary, the whole column when its length changed.
+    pub fn refresh_footer(&mut self, col_id: usize) {
+        let column = &self.text_data.columns[col_id];
+        let grid = match &column.grid {
+            Some(grid) => grid,
+            None => return,
+        };
+        let (first, lines) = (grid.footer_start(), grid.footer_lines());
+        if first + lines.len() == column.length {
+            replace_lines(
+                &mut self.text_data,
+                &mut self.text_component,
+                col_id,
+                first,
+                &lines,
+            );
+        } else {
+            // the footer changed length, sums and averages come and go with the numbers
+            let lines = grid.lines(&mut self.text_component);
+            replace_text(
+                &mut self.text_data,
+                &mut self.text_component,
+                col_id,
+                &lines,
+            );
+        }
+    }
+
+    // Every grid column's summary starts over from the rows now shown.
+    pub fn reaggregate(&mut self) {
+        for (col_id, column) in self.text_data.columns.iter().enumerate() {
+            if let Some(grid) = &column.grid {
+                let cells = self
+                    .rows
+                    .shown(grid.cells.len())
+                    .into_iter()
+                    .map(|row| grid.cells[row].clone())
+                    .collect();
+                self.aggregates.restart(col_id, cells);
+            }
         }
     }
 }
 
-const LINE_HEIGHT: f32 = 80.0;
+// Logical pixels, like every other position and size.
+const FONT_SIZE: f32 = 41.0;
+const LINE_HEIGHT: f32 = 40.0;
+// how far glyphs hang below the baseline, roughly, at FONT_SIZE
+const LINE_DESCENT: f32 = 10.0;
+const SCROLL_SPEED: f32 = 10.0;
+const SCROLL_GLIDE: f32 = 0.08; // seconds
+const ERRORS_SHOWN: usize = 5;
+const ERROR_FADE: f32 = 0.3; // seconds for the error overlay to fade in
+const ERROR_PADDING: f32 = 10.0; // the error overlay's backdrop around its text
+
+fn column_width(text_data: &TextData, column: &Column) -> f32 {
+    let lines = &text_data.bound_lines[column.offset..column.offset + column.length];
+    column
+        .grid
+        .as_ref()
+        .and_then(|grid| grid.format.max_width)
+        .unwrap_or_else(|| lines.iter().fold(0.0, |w, line| w.max(line.width)))
+}
 
-impl Column {
-    fn cur_pos(&self, at_time: f64) -> Vec2 {
-        match &self.animation {
-            Some(anim) => {
-                if at_time <= anim.start_time {
-                    anim.prev_pos
-                } else if at_time >= anim.start_time + anim.duration as f64 {
-                    self.pos
-                } else {
-                    let t = (at_time - anim.start_time) as f32 / anim.duration;
-                    let x = (1.0 - t) * anim.prev_pos.x + t * self.pos.x;
-                    let y = (1.0 - t) * anim.prev_pos.y + t * self.pos.y;
-                    Vec2 { x, y }
-                }
+fn intersect_clip(a: Clip, b: Option<Clip>) -> Clip {
+    match b {
+        None => a,
+        Some(b) => {
+            let x = a.pos.x.max(b.pos.x);
+            let y = a.pos.y.max(b.pos.y);
+            let right = (a.pos.x + a.size.x).min(b.pos.x + b.size.x);
+            let bottom = (a.pos.y + a.size.y).min(b.pos.y + b.size.y);
+            Clip {
+                pos: Vec2 { x, y },
+                size: Vec2 {
+                    x: (right - x).max(0.0),
+                    y: (bottom - y).max(0.0),
+                },
             }
-            None => self.pos,
         }
     }
 }
 
-fn draw_column(
-    text_data: &TextData,
-    window_width: f32,
-    window_height: f32,
+// What every column is drawn against this frame.
+struct Frame<'a> {
+    text_data: &'a TextData,
+    screen: Screen,
+    at_time: f64,
+    theme: &'a Theme,
+}
+
+// A run of a column's lines: the first one's baseline, what of them shows and how they're
+// tinted.
+#[derive(Debug, Clone, Copy)]
+struct LineStyle {
+    pos: Vec2,
+    clip: Option<Clip>,
+    opacity: f32,
+    color: [f32; 4],
+}
+
+// One line under the other from the style's pos, which is where the first of them goes.
+fn draw_lines(
     ctx: &mut Box<dyn RenderingBackend>,
+    frame: &Frame,
     column: &Column,
-    at_time: f64,
+    lines: &[usize],
+    style: LineStyle,
 ) {
-    match column.clip {
-        Some(clip) => {
-            ctx.apply_scissor_rect(
-                // This is in "real" pixels i.e. not the halved coarse pixels as reported by eg screen shot tool
-                clip.pos.x as i32,
-                (window_height - (clip.pos.y + clip.size.y)) as i32,
-                clip.size.x as i32,
-                clip.size.y as i32,
-            );
-        }
-        None => {}
+    let LineStyle {
+        pos,
+        clip,
+        opacity,
+        color,
+    } = style;
+    let (text_data, screen) = (frame.text_data, frame.screen);
+    if lines.is_empty() || opacity <= 0.0 {
+        return;
     }
-    let pos = column.cur_pos(at_time);
-    let mut cur_y = pos.y;
-    for text_line in text_data.bound_lines[column.offset..column.offset + column.length].iter() {
+    if let Some(clip) = clip {
+        scissor(ctx, screen, clip);
+    }
+    let width = column_width(text_data, column);
+    for (slot, &i) in lines.iter().enumerate() {
+        let text_line = &text_data.bound_lines[column.offset + i];
+        let align = match &column.grid {
+            Some(grid) => grid.line_align(i),
+            None => Align::Left,
+        };
+        let x = match align {
+            Align::Left => pos.x,
+            Align::Right => pos.x + width - text_line.width,
+            Align::Center => pos.x + (width - text_line.width) / 2.0,
+        };
         ctx.apply_bindings(&text_line.bindings);
         ctx.apply_uniforms(UniformsSource::table(&shader::Uniforms {
-            offset: (pos.x, cur_y),
-            window_scale: (2.0 / window_width.max(0.1), -2.0 / window_height.max(0.1)),
+            color,
+            offset: (
+                screen.snap(x),
+                screen.snap(pos.y + slot as f32 * LINE_HEIGHT),
+            ),
+            window_scale: (2.0 / screen.width.max(0.1), -2.0 / screen.height.max(0.1)),
+            opacity: opacity.min(1.0),
         }));
         ctx.draw(0, text_line.index_count, 1);
-        cur_y += LINE_HEIGHT;
     }
-    match column.clip {
-        Some(_clip) => {
-            ctx.apply_scissor_rect(0, 0, window_width as i32, window_height as i32);
-        }
-        None => {}
+    if clip.is_some() {
+        unscissor(ctx, screen);
+    }
+}
+
+fn scissor(ctx: &mut Box<dyn RenderingBackend>, screen: Screen, clip: Clip) {
+    // scissor rects are in physical pixels, from the bottom left
+    let scale = screen.dpi_scale;
+    ctx.apply_scissor_rect(
+        (clip.pos.x * scale) as i32,
+        ((screen.height - (clip.pos.y + clip.size.y)) * scale) as i32,
+        (clip.size.x * scale) as i32,
+        (clip.size.y * scale) as i32,
+    );
+}
+
+fn unscissor(ctx: &mut Box<dyn RenderingBackend>, screen: Screen) {
+    ctx.apply_scissor_rect(
+        0,
+        0,
+        (screen.width * screen.dpi_scale) as i32,
+        (screen.height * screen.dpi_scale) as i32,
+    );
+}
+
+// The layout's splitter dividers, all in one buffer that's refilled each frame, each quad drawn
+// scissored to what of it shows. The one being dragged is in the accent.
+fn draw_dividers(
+    ctx: &mut Box<dyn RenderingBackend>,
+    screen: Screen,
+    solid: &Solid,
+    layout: &Layout,
+    theme: &Theme,
+) {
+    let dividers = layout.dividers();
+    let dividers = &dividers[..dividers.len().min(MAX_SOLIDS)];
+    if dividers.is_empty() {
+        return;
+    }
+    let vertices: Vec<Vertex> = dividers
+        .iter()
+        .flat_map(|divider| solid.quad(divider.rect))
+        .collect();
+    ctx.buffer_update(
+        solid.bindings.vertex_buffers[0],
+        BufferSource::slice(&vertices),
+    );
+    ctx.apply_bindings(&solid.bindings);
+    for (i, divider) in dividers.iter().enumerate() {
+        let role = if layout.dragging() == Some(divider.name.as_str()) {
+            Role::Accent
+        } else {
+            Role::GridLine
+        };
+        ctx.apply_uniforms(UniformsSource::table(&shader::Uniforms {
+            color: theme.color(role),
+            offset: (0.0, 0.0),
+            window_scale: (2.0 / screen.width.max(0.1), -2.0 / screen.height.max(0.1)),
+            opacity: 1.0,
+        }));
+        scissor(ctx, screen, divider.clip);
+        ctx.draw(i as i32 * 6, 6, 1);
+    }
+    unscissor(ctx, screen);
+}
+
+// Over everything else, on the background color so the columns under it don't show through.
+fn draw_error_overlay(
+    ctx: &mut Box<dyn RenderingBackend>,
+    frame: &Frame,
+    solid: &Solid,
+    column: &Column,
+) {
+    let (screen, at_time) = (frame.screen, frame.at_time);
+    let opacity = column.opacity.at(at_time);
+    if column.length == 0 || opacity <= 0.0 {
+        return;
+    }
+    let pos = column.pos.at(at_time);
+    let rect = Clip {
+        pos: Vec2 {
+            x: pos.x - ERROR_PADDING,
+            y: pos.y - (LINE_HEIGHT - LINE_DESCENT) - ERROR_PADDING,
+        },
+        size: Vec2 {
+            x: column_width(frame.text_data, column) + 2.0 * ERROR_PADDING,
+            y: column.length as f32 * LINE_HEIGHT + 2.0 * ERROR_PADDING,
+        },
+    };
+    ctx.buffer_update(
+        solid.bindings.vertex_buffers[0],
+        BufferSource::slice(&solid.quad(rect)),
+    );
+    ctx.apply_bindings(&solid.bindings);
+    ctx.apply_uniforms(UniformsSource::table(&shader::Uniforms {
+        color: frame.theme.color(Role::Background),
+        offset: (0.0, 0.0),
+        window_scale: (2.0 / screen.width.max(0.1), -2.0 / screen.height.max(0.1)),
+        opacity,
+    }));
+    ctx.draw(0, 6, 1);
+    draw_column(ctx, frame, column, &GridRows::default());
+}
+
+// Text in its lines' order, a grid column outside the grid view in its shown rows'.
+fn draw_column(
+    ctx: &mut Box<dyn RenderingBackend>,
+    frame: &Frame,
+    column: &Column,
+    rows: &GridRows,
+) {
+    let at_time = frame.at_time;
+    let mut lines = shown_lines(column, rows);
+    lines.extend(footer_range(column));
+    let style = LineStyle {
+        pos: column.pos.at(at_time),
+        clip: column.clip.at(at_time),
+        opacity: column.opacity.at(at_time),
+        color: column_color(column, at_time, frame.theme),
+    };
+    draw_lines(ctx, frame, column, &lines, style);
+}
+
+// Its role's color in the theme, or where animate_color has it now.
+fn column_color(column: &Column, at_time: f64, theme: &Theme) -> [f32; 4] {
+    column.color.at(at_time).unwrap_or(theme.color(column.role))
+}
+
+// The lines a column shows in order: all of them, or for a grid column its header and then its
+// cells in the shown rows' order.
+fn shown_lines(column: &Column, rows: &GridRows) -> Vec<usize> {
+    match &column.grid {
+        Some(grid) => std::iter::once(0)
+            .chain(rows.shown(grid.cells.len()).into_iter().map(|row| row + 1))
+            .filter(|&line| line < column.length)
+            .collect(),
+        None => (0..column.length).collect(),
+    }
+}
+
+// A grid column's footer lines, after its cells.
+fn footer_range(column: &Column) -> Range<usize> {
+    match &column.grid {
+        Some(grid) => grid.footer_start().min(column.length)..column.length,
+        None => 0..0,
+    }
+}
+
+// Right edge of the pinned grid columns, which is where the sideways scrolling area starts.
+fn pinned_right(text_data: &TextData, view: &GridView) -> f32 {
+    text_data
+        .columns
+        .iter()
+        .filter(|column| column.grid.is_some())
+        .take(view.pinned_columns)
+        .fold(view.pos.x, |right, column| {
+            right.max(column.pos.target().x + column_width(text_data, column))
+        })
+        .min(view.pos.x + view.size.x)
+}
+
+// Each grid column is drawn in three bands, its frozen rows, its body and its footer at the
+// bottom, and each band is scissored to either the pinned strip or the scrolling area so the
+// frozen parts cover the body. The footer scrolls sideways with the body but never up and down.
+fn draw_grid_column(
+    ctx: &mut Box<dyn RenderingBackend>,
+    frame: &Frame,
+    column: &Column,
+    view: &GridView,
+    lines: &[usize],
+    pinned: bool,
+    pinned_right: f32,
+) {
+    let (at_time, theme) = (frame.at_time, frame.theme);
+    let pos = column.pos.at(at_time);
+    let scroll = view.scroll.at(at_time);
+    let opacity = column.opacity.at(at_time);
+    let (left, right, x) = if pinned {
+        (view.pos.x, pinned_right, pos.x)
+    } else {
+        (pinned_right, view.pos.x + view.size.x, pos.x - scroll.x)
+    };
+    let bottom = view.pos.y + view.size.y;
+    let header_bottom = (view.pos.y + view.frozen_rows as f32 * LINE_HEIGHT).min(bottom);
+    let footer: Vec<usize> = footer_range(column).collect();
+    let footer_top = (bottom - footer.len() as f32 * LINE_HEIGHT).max(header_bottom);
+    let band = |top: f32, bottom: f32| {
+        Some(intersect_clip(
+            Clip {
+                pos: Vec2 { x: left, y: top },
+                size: Vec2 {
+                    x: (right - left).max(0.0),
+                    y: (bottom - top).max(0.0),
+                },
+            },
+            column.clip.at(at_time),
+        ))
+    };
+    let baseline = view.pos.y + LINE_HEIGHT - LINE_DESCENT;
+    let frozen = view.frozen_rows.min(lines.len());
+    let color = column_color(column, at_time, theme);
+    // headers stand out in the accent and footers are muted, unless Lean gave the column a role
+    // or a color of its own
+    let (header_color, footer_color) = match (column.role, column.color.target()) {
+        (Role::Text, None) => (theme.color(Role::Accent), theme.color(Role::Muted)),
+        _ => (color, color),
+    };
+    let header = LineStyle {
+        pos: Vec2 { x, y: baseline },
+        clip: band(view.pos.y, header_bottom),
+        opacity,
+        color: header_color,
+    };
+    draw_lines(ctx, frame, column, &lines[..frozen], header);
+    let body = LineStyle {
+        pos: Vec2 {
+            x,
+            y: baseline - scroll.y + frozen as f32 * LINE_HEIGHT,
+        },
+        clip: band(header_bottom, footer_top),
+        opacity,
+        color,
+    };
+    draw_lines(ctx, frame, column, &lines[frozen..], body);
+    let footer_style = LineStyle {
+        pos: Vec2 {
+            x,
+            y: footer_top + LINE_HEIGHT - LINE_DESCENT,
+        },
+        clip: band(footer_top, bottom),
+        opacity,
+        color: footer_color,
+    };
+    draw_lines(ctx, frame, column, &footer, footer_style);
+}
+
+// In case the Stage goes without a quit_requested_event, a no-op after one.
+impl Drop for Stage {
+    fn drop(&mut self) {
+        self.shut_down();
     }
 }
 
 impl EventHandler for Stage {
-    fn update(&mut self) {}
+    fn update(&mut self) {
+        self.update_dpi_scale();
+        if self.watcher.poll() {
+            self.reload_lean();
+        }
+        if let Some(saved) = &mut self.saved_state {
+            if let Err(e) = saved.autosave(&self.interp) {
+                self.interp.report_error("autosave", e);
+            }
+        }
+        for (col_id, summary) in self.aggregates.poll() {
+            if let Some(grid) = &mut self.text_data.columns[col_id].grid {
+                grid.summary = Some(summary);
+                self.refresh_footer(col_id);
+                self.send_event(Event::DataReady { col: col_id as u64 });
+            }
+        }
+        let now = date::now();
+        for timer in self.timers.due(now) {
+            if self.timers.fire(timer, now) {
+                self.send_event(Event::Timer { timer });
+            }
+        }
+        if self.timers.take_frame() {
+            self.send_event(Event::Tick { time: now });
+        }
+        self.show_errors();
+    }
 
     fn draw(&mut self) {
         if self.draws_remaining <= 0 {
@@ -659,66 +1442,185 @@ impl EventHandler for Stage {
 
         self.ctx.begin_default_pass(Default::default());
 
-        self.ctx.clear(Some(BACKGROUND_COLOR), None, None);
+        let [r, g, b, a] = self.theme.color(Role::Background);
+        self.ctx.clear(Some((r, g, b, a)), None, None);
 
         self.ctx.apply_pipeline(&self.pipeline);
-        for column in self.text_data.columns.iter() {
-            draw_column(
-                &self.text_data,
-                self.window_width,
-                self.window_height,
-                &mut self.ctx,
-                column,
-                t,
-            );
+        let pinned_right = self
+            .grid_view
+            .as_ref()
+            .map(|view| pinned_right(&self.text_data, view));
+        let frame = Frame {
+            text_data: &self.text_data,
+            screen: self.screen,
+            at_time: t,
+            theme: &self.theme,
+        };
+        let mut grid_index = 0;
+        for (col_id, column) in self.text_data.columns.iter().enumerate() {
+            if col_id == self.error_overlay {
+                continue;
+            }
+            match (&self.grid_view, &column.grid, pinned_right) {
+                (Some(view), Some(_), Some(pinned_right)) => {
+                    draw_grid_column(
+                        &mut self.ctx,
+                        &frame,
+                        column,
+                        view,
+                        &shown_lines(column, &self.rows),
+                        grid_index < view.pinned_columns,
+                        pinned_right,
+                    );
+                    grid_index += 1;
+                }
+                _ => draw_column(&mut self.ctx, &frame, column, &self.rows),
+            }
         }
+        draw_dividers(
+            &mut self.ctx,
+            self.screen,
+            &self.dividers,
+            &self.layout,
+            &self.theme,
+        );
+        draw_error_overlay(
+            &mut self.ctx,
+            &frame,
+            &self.error_backdrop,
+            &self.text_data.columns[self.error_overlay],
+        );
         self.ctx.end_render_pass();
 
         self.ctx.commit_frame();
     }
 
+    fn mouse_wheel_event(&mut self, x: f32, y: f32) {
+        self.scroll_grid(-x * SCROLL_SPEED, -y * SCROLL_SPEED);
+    }
+
+    // Every quit comes through here, Lean's and the Q key request it too. On macOS it's also the
+    // last chance, the app exits without dropping the Stage.
+    fn quit_requested_event(&mut self) {
+        self.shut_down();
+    }
+
+    // miniquad reports sizes and the mouse in physical pixels.
     fn resize_event(&mut self, w: f32, h: f32) {
-        self.window_width = w;
-        self.window_height = h;
+        self.update_dpi_scale();
+        self.screen.width = w / self.screen.dpi_scale;
+        self.screen.height = h / self.screen.dpi_scale;
+        self.apply_layout();
+        self.send_event(Event::Resize {
+            width: self.screen.width,
+            height: self.screen.height,
+        });
+    }
+
+    // A drag on a splitter's divider is the layout's, Lean only hears where it was let go.
+    fn mouse_motion_event(&mut self, x: f32, y: f32) {
+        let (x, y) = (x / self.screen.dpi_scale, y / self.screen.dpi_scale);
+        if self.layout.drag_to(Vec2 { x, y }) {
+            self.apply_layout();
+            return;
+        }
+        self.send_event(Event::Mouse {
+            kind: MouseKind::Move,
+            x,
+            y,
+            button: 3,
+        });
+    }
+
+    fn mouse_button_down_event(&mut self, button: MouseButton, x: f32, y: f32) {
+        let (x, y) = (x / self.screen.dpi_scale, y / self.screen.dpi_scale);
+        if button == MouseButton::Left && self.layout.start_drag(Vec2 { x, y }) {
+            return;
+        }
+        self.send_event(Event::Mouse {
+            kind: MouseKind::Down,
+            x,
+            y,
+            button: mouse_button_index(button),
+        });
+    }
+
+    fn mouse_button_up_event(&mut self, button: MouseButton, x: f32, y: f32) {
+        if button == MouseButton::Left {
+            if let Some((name, ratio)) = self.layout.end_drag() {
+                self.send_event(Event::Splitter { name, ratio });
+                return;
+            }
+        }
+        self.send_event(Event::Mouse {
+            kind: MouseKind::Up,
+            x: x / self.screen.dpi_scale,
+            y: y / self.screen.dpi_scale,
+            button: mouse_button_index(button),
+        });
     }
 
     fn char_event(&mut self, character: char, _keymods: KeyMods, _repeat: bool) {
-        send_event_to_lean(&mut self.interp, 1, character as u32);
-        perform_effects(self);
+        self.send_event(Event::Char(character));
+    }
+
+    fn key_up_event(&mut self, keycode: KeyCode, _keymods: KeyMods) {
+        self.send_event(Event::Key {
+            name: format!("{:?}", keycode),
+            down: false,
+        });
     }
 
     fn key_down_event(&mut self, keycode: KeyCode, _keymods: KeyMods, _repeat: bool) {
+        self.send_event(Event::Key {
+            name: format!("{:?}", keycode),
+            down: true,
+        });
         match keycode {
             KeyCode::Up => {
                 let t = date::now();
-                if self.text_data.columns.len() > 0 {
-                    self.text_data.columns[0].animation = Some(Animating {
-                        prev_pos: self.text_data.columns[0].cur_pos(t),
-                        duration: 0.1,
-                        start_time: t,
-                    });
-                    self.text_data.columns[0].pos.y -= 50.0;
+                // main's first text column, the overlay comes before it
+                let col_id = self.error_overlay + 1;
+                if col_id < self.builtin_columns {
+                    let pos = &mut self.text_data.columns[col_id].pos;
+                    let to = Vec2 {
+                        x: pos.target().x,
+                        y: pos.target().y - 25.0,
+                    };
+                    pos.animate(t, to, 0.1, Easing::Linear);
                 }
             }
             KeyCode::Down => {
                 let t = date::now();
-                if self.text_data.columns.len() > 0 {
-                    self.text_data.columns[0].animation = Some(Animating {
-                        prev_pos: self.text_data.columns[0].cur_pos(t),
-                        duration: 1.0,
-                        start_time: t,
-                    });
-                    self.text_data.columns[0].pos.y += 50.0;
+                // main's first text column, the overlay comes before it
+                let col_id = self.error_overlay + 1;
+                if col_id < self.builtin_columns {
+                    let pos = &mut self.text_data.columns[col_id].pos;
+                    let to = Vec2 {
+                        x: pos.target().x,
+                        y: pos.target().y + 25.0,
+                    };
+                    pos.animate(t, to, 1.0, Easing::Linear);
                 }
             }
             KeyCode::Q => {
-                window::quit();
+                window::request_quit();
             }
             _ => {}
         }
     }
 }
 
+// the button field of Event.mouse
+fn mouse_button_index(button: MouseButton) -> u8 {
+    match button {
+        MouseButton::Left => 0,
+        MouseButton::Right => 1,
+        MouseButton::Middle => 2,
+        MouseButton::Unknown => 3,
+    }
+}
+
 fn draw_rect(arr: &mut [u8; 400 * 200 * 4], x: usize, y: usize, w: usize, h: usize, color: Color) {
     for j in 0..h {
         for i in 0..w {
@@ -730,108 +1632,403 @@ fn draw_rect(arr: &mut [u8; 400 * 200 * 4], x: usize, y: usize, w: usize, h: usi
     }
 }
 
-fn perform_effects(stage: &mut Stage) {
-    //let interp = &mut stage.interp;
-    for (id, pos) in stage.interp.effects.new_columns.iter() {
-        let sid = match stage.interp.effects.text.get(id) {
-            None => insert_text(
-                &mut stage.text_data,
-                &mut stage.text_component,
-                Vec2 { x: pos.x, y: pos.y },
-                None,
-                &vec![],
-            ),
-            Some((_app, lines)) => insert_text(
-                &mut stage.text_data,
-                &mut stage.text_component,
-                Vec2 { x: pos.x, y: pos.y },
-                None,
-                lines,
-            ),
-        };
+fn to_clip(clip: &lean_experiments::gui_api::Clip) -> Clip {
+    Clip {
+        pos: Vec2 {
+            x: clip.pos.x,
+            y: clip.pos.y,
+        },
+        size: Vec2 {
+            x: clip.size.x,
+            y: clip.size.y,
+        },
+    }
+}
 
-        assert!(sid == *id as usize, "{},{}", sid, id);
+// The text of a column as it was last laid out.
+fn column_lines(text_data: &TextData, col_id: usize) -> Vec<String> {
+    let column = &text_data.columns[col_id];
+    text_data.laid_out_lines[column.offset..column.offset + column.length]
+        .iter()
+        .map(|line| line.text().to_owned())
+        .collect()
+}
+
+fn perform_effects(stage: &mut Stage) {
+    let queue = std::mem::take(&mut stage.interp.effects.queue);
+    // Laying out text is the slow part, so effects change what a column should hold and each
+    // touched column is laid out once at the end.
+    let mut text: BTreeMap<usize, Vec<String>> = BTreeMap::new();
+    let mut grids: BTreeSet<usize> = BTreeSet::new();
+    let mut reaggregate = false;
+    let mut clamp_scroll = false;
+    let mut relayout = false;
+    for effect in queue {
+        // a stale or made-up id from a script is its bug, reported and skipped
+        if let Some(col) = effect.col() {
+            let columns = stage.text_data.columns.len();
+            let wrong = match effect {
+                Effect::FreshColumn { .. } if col as usize != columns => Some(format!(
+                    "column {} made where column {} was next",
+                    col, columns
+                )),
+                Effect::FreshColumn { .. } => None,
+                _ if col as usize >= columns => Some(format!("no column {}", col)),
+                _ => None,
+            };
+            if let Some(e) = wrong {
+                stage.interp.report_error("effect", e);
+                continue;
+            }
+        }
+        match effect {
+            Effect::FreshColumn { pos, .. } => {
+                insert_text(
+                    &mut stage.text_data,
+                    &mut stage.text_component,
+                    Vec2 { x: pos.x, y: pos.y },
+                    None,
+                    &vec![],
+                );
+                // the layout may have a place for it already
+                relayout = true;
+            }
+            Effect::PushLine { col, line } => {
+                let col_id = col as usize;
+                text.entry(col_id)
+                    .or_insert_with(|| column_lines(&stage.text_data, col_id))
+                    .push(line);
+            }
+            Effect::ResetText { col } => {
+                text.insert(col as usize, vec![]);
+            }
+            Effect::SetClip { col, clip } => {
+                stage.text_data.columns[col as usize]
+                    .clip
+                    .set(clip.map(|clip| to_clip(&clip)));
+            }
+            Effect::Animate {
+                col,
+                to,
+                duration,
+                easing,
+                after,
+            } => {
+                let pos = &mut stage.text_data.columns[col as usize].pos;
+                let to = Vec2 { x: to.x, y: to.y };
+                if after {
+                    pos.then(date::now(), to, duration, easing);
+                } else {
+                    pos.animate(date::now(), to, duration, easing);
+                }
+            }
+            Effect::AnimateClip {
+                col,
+                to,
+                duration,
+                easing,
+                after,
+            } => {
+                let clip = &mut stage.text_data.columns[col as usize].clip;
+                let to = Some(to_clip(&to));
+                if after {
+                    clip.then(date::now(), to, duration, easing);
+                } else {
+                    clip.animate(date::now(), to, duration, easing);
+                }
+            }
+            Effect::AnimateColor {
+                col,
+                to,
+                duration,
+                easing,
+                after,
+            } => {
+                let column = &mut stage.text_data.columns[col as usize];
+                // starts from the role's color, the first time
+                if column.color.target().is_none() {
+                    column.color.set(Some(stage.theme.color(column.role)));
+                }
+                if after {
+                    column.color.then(date::now(), Some(to), duration, easing);
+                } else {
+                    column
+                        .color
+                        .animate(date::now(), Some(to), duration, easing);
+                }
+            }
+            Effect::Fade {
+                col,
+                to,
+                duration,
+                easing,
+                after,
+            } => {
+                let opacity = &mut stage.text_data.columns[col as usize].opacity;
+                let to = to.clamp(0.0, 1.0);
+                if after {
+                    opacity.then(date::now(), to, duration, easing);
+                } else {
+                    opacity.animate(date::now(), to, duration, easing);
+                }
+            }
+            Effect::SetColumnRole { col, role } => {
+                let column = &mut stage.text_data.columns[col as usize];
+                column.role = role;
+                column.color.set(None);
+            }
+            // glyphs are white in the atlas, only the color uniforms change
+            Effect::SetTheme(theme) => stage.theme = theme,
+            Effect::AnimateScroll {
+                to,
+                duration,
+                easing,
+                after,
+            } => match &mut stage.grid_view {
+                Some(view) => {
+                    let to = Vec2 { x: to.x, y: to.y };
+                    if after {
+                        view.scroll.then(date::now(), to, duration, easing);
+                    } else {
+                        view.scroll.animate(date::now(), to, duration, easing);
+                    }
+                    clamp_scroll = true;
+                }
+                None => println!("animate_scroll with no grid view"),
+            },
+            Effect::PushCell { col, cell } => {
+                let cells = &mut stage.grid_column(col as usize).cells;
+                let row = cells.len();
+                cells.push(cell.clone());
+                // footers sum up the shown rows, a row Lean filtered away stays out
+                if stage.rows.is_shown(row) {
+                    stage.aggregates.extend(col as usize, vec![cell]);
+                }
+                grids.insert(col as usize);
+            }
+            Effect::SetFormat { col, format } => {
+                stage.grid_column(col as usize).format = format;
+                grids.insert(col as usize);
+            }
+            Effect::SetHeader { col, name } => {
+                stage.grid_column(col as usize).name = name;
+                grids.insert(col as usize);
+            }
+            Effect::SetShownRows(rows) => {
+                stage.rows.shown = rows;
+                reaggregate = true;
+                clamp_scroll = true;
+            }
+            Effect::SetSelection { rows, columns } => {
+                stage.rows.selected = rows.into_iter().collect();
+                stage.rows.selected_columns = columns.into_iter().map(|col| col as usize).collect();
+            }
+            Effect::ExportGrid { format, path } => {
+                let columns: Vec<&GridColumn> = stage
+                    .text_data
+                    .columns
+                    .iter()
+                    .enumerate()
+                    .filter(|(col_id, _)| stage.rows.column_selected(*col_id))
+                    .filter_map(|(_, column)| column.grid.as_ref())
+                    .collect();
+                let row_count = columns.iter().map(|c| c.cells.len()).max().unwrap_or(0);
+                let rows = stage.rows.exported(row_count);
+                match export::export_grid(&path, &format, &columns, &rows) {
+                    Ok(rows) => println!("exported {} rows to {}", rows, path),
+                    Err(e) => stage
+                        .interp
+                        .report_error("export", format!("{}: {}", path, e)),
+                }
+            }
+            Effect::SetFooter { col, kinds } => {
+                stage.grid_column(col as usize).footer = kinds;
+                grids.insert(col as usize);
+            }
+            Effect::SetGridView(spec) => {
+                let scroll = stage
+                    .grid_view
+                    .take()
+                    .map_or(Animated::new(Vec2 { x: 0.0, y: 0.0 }), |view| view.scroll);
+                stage.grid_view = Some(GridView {
+                    pos: Vec2 {
+                        x: spec.pos.x,
+                        y: spec.pos.y,
+                    },
+                    size: Vec2 {
+                        x: spec.size.x,
+                        y: spec.size.y,
+                    },
+                    frozen_rows: spec.frozen_rows,
+                    pinned_columns: spec.pinned_columns,
+                    scroll,
+                });
+                clamp_scroll = true;
+            }
+            Effect::SetLayout(node) => relayout |= stage.layout.set_root(node),
+            Effect::RequestTimer {
+                timer,
+                delay,
+                every,
+            } => stage.timers.request(timer, date::now(), delay, every),
+            Effect::CancelTimer { timer } => stage.timers.cancel(timer),
+            Effect::RequestFrame => stage.timers.request_frame(),
+            Effect::Quit => window::request_quit(),
+        }
     }
-    for (id, (app, lines)) in stage.interp.effects.text.iter() {
-        let nc = stage.interp.effects.new_columns.get(id);
 
-        // i.e. we're adjusting text on a column that wasn't introduced this event
-        if nc.is_none() {
-            // don't support appending pre-existing text yet
-            assert!(matches!(app, AppendMode::Replace));
-            // don't support changing length yet
+    for (col_id, lines) in text.iter() {
+        replace_text(
+            &mut stage.text_data,
+            &mut stage.text_component,
+            *col_id,
+            lines,
+        );
+    }
+    for col_id in grids.iter() {
+        if let Some(grid) = &stage.text_data.columns[*col_id].grid {
+            let lines = grid.lines(&mut stage.text_component);
             replace_text(
                 &mut stage.text_data,
                 &mut stage.text_component,
-                *id as usize,
-                lines,
+                *col_id,
+                &lines,
             );
         }
     }
 
-    for (id, clip) in stage.interp.effects.clip.iter() {
-        stage.text_data.columns[*id as usize].clip = match clip {
-            None => None,
-            Some(clip) => Some(Clip {
-                pos: Vec2 {
-                    x: clip.pos.x,
-                    y: clip.pos.y,
-                },
-                size: Vec2 {
-                    x: clip.size.x,
-                    y: clip.size.y,
-                },
-            }),
-        }
+    if reaggregate {
+        stage.reaggregate();
     }
 
-    for (id, animate) in stage.interp.effects.animate.iter() {
-        let t = date::now();
-        stage.text_data.columns[*id as usize].animation = Some(Animating {
-            prev_pos: stage.text_data.columns[0].cur_pos(t),
-            duration: animate.1,
-            start_time: t,
-        });
-        stage.text_data.columns[*id as usize].pos.x = animate.0.x;
-        stage.text_data.columns[*id as usize].pos.y = animate.0.y;
+    if relayout {
+        stage.apply_layout();
     }
 
-    if stage.interp.effects.should_quit {
-        window::quit();
+    if clamp_scroll {
+        // re-clamps the kept scroll against the new size
+        stage.scroll_grid(0.0, 0.0);
     }
-
-    stage.interp.effects.new_columns = BTreeMap::new();
-    stage.interp.effects.text = HashMap::new();
-    stage.interp.effects.clip = HashMap::new();
-    stage.interp.effects.animate = HashMap::new();
-    stage.interp.effects.should_quit = false;
 }
 
 fn main() {
-    let mut interp = lean_experiments::test_lean();
+    // Regenerates the Lean side of the effect bindings, without starting Lean.
+    if std::env::args().nth(1).as_deref() == Some("--emit-lean") {
+        let path = std::env::args()
+            .nth(2)
+            .unwrap_or(String::from("../lean/Structural/Gui.lean"));
+        std::fs::write(&path, lean_experiments::bindings::gui_lean_source()).unwrap();
+        println!("wrote {}", path);
+        return;
+    }
+    // Checks that underscores and box drawing join up at several scales, without a window.
+    if std::env::args().nth(1).as_deref() == Some("--check-glyphs") {
+        match glyph_check::check_joins(&mut TextComponent::new()) {
+            Ok(checked) => println!("{} lines join up", checked),
+            Err(errors) => {
+                for e in errors {
+                    println!("{}", e);
+                }
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    let args: Vec<String> = std::env::args().collect();
+    let flag_value = |flag: &str| {
+        args.iter()
+            .position(|arg| arg == flag)
+            .and_then(|i| args.get(i + 1).cloned())
+    };
 
+    let lib_path = flag_value("--structural")
+        .map(PathBuf::from)
+        .unwrap_or_else(structural::default_path);
+    // A session log starts from leanOnInit, so recording, replaying and benchmarking leave saved
+    // state alone.
+    let fresh = ["--record", "--replay", "--bench-events"]
+        .iter()
+        .any(|flag| flag_value(flag).is_some());
+    let mut saved = if fresh {
+        None
+    } else {
+        Some(SavedState::new(
+            flag_value("--state")
+                .map(PathBuf::from)
+                .unwrap_or(PathBuf::from("tabularasa.state")),
+        ))
+    };
+    let error_log = flag_value("--error-log")
+        .map(PathBuf::from)
+        .unwrap_or(PathBuf::from("tabularasa-errors.log"));
+    let mut interp = lean_experiments::test_lean(&lib_path, saved.as_mut(), Some(error_log));
+    let theme = match flag_value("--theme") {
+        Some(spec) => Theme::load(&spec).unwrap_or_else(|e| {
+            println!("{}, using the light theme", e);
+            Theme::light()
+        }),
+        None => Theme::light(),
+    };
+
+    // Replays a session log against the current libStructural without opening a window, exiting
+    // non-zero when the effects differ.
+    if let Some(path) = flag_value("--replay") {
+        let result = session::replay(&mut interp, &path);
+        let loaded = interp.lib.is_some();
+        interp.shutdown();
+        let code = match result {
+            Ok(0) if loaded => 0,
+            Ok(_) => 1,
+            Err(e) => {
+                println!("replay failed: {}", e);
+                2
+            }
+        };
+        std::process::exit(code);
+    }
+    // Events per second through leanOnEvent, no window. The audit at shutdown shows whether
+    // anything leaked along the way.
+    if let Some(count) = flag_value("--bench-events") {
+        let count = match count.parse::<usize>() {
+            Ok(count) if count > 0 => count,
+            _ => {
+                println!("--bench-events takes a number of events, got {}", count);
+                std::process::exit(2);
+            }
+        };
+        session::bench_events(&mut interp, count);
+        let loaded = interp.lib.is_some();
+        interp.shutdown();
+        std::process::exit(if loaded { 0 } else { 1 });
+    }
+    if let Some(path) = flag_value("--record") {
+        match session::SessionLog::create(&path) {
+            Ok(log) => interp.log = Some(log),
+            Err(e) => println!("not recording: {}", e),
+        }
+    }
     let mut conf = conf::Conf::default();
-    let metal = std::env::args().nth(1).as_deref() == Some("metal");
+    let metal = args.iter().any(|arg| arg == "metal");
     conf.platform.apple_gfx_api = if metal {
         conf::AppleGfxApi::Metal
     } else {
         conf::AppleGfxApi::OpenGl
     };
+    // a framebuffer at the display's resolution, Stage works out the scale once it's up
     conf.high_dpi = true;
     println!("Conf.width {}x{}", conf.window_width, conf.window_height);
 
-    let window_width = conf.window_width as f32 * 2.0; // not sure we can get dpi_scale before starting
-    let window_height = conf.window_height as f32 * 2.0;
     miniquad::start(conf, move || {
         Box::new({
-            let mut stage = Stage::new(window_width, window_height, interp);
+            let mut stage = Stage::new(interp, Watcher::new(lib_path), saved, theme);
             insert_text(
                 &mut stage.text_data,
                 &mut stage.text_component,
-                Vec2 { x: 100.0, y: 100.0 },
+                Vec2 { x: 50.0, y: 50.0 },
                 Some(Clip {
-                    pos: Vec2 { x: 108.0, y: 100.0 },
-                    size: Vec2 { x: 560.0, y: 560.0 },
+                    pos: Vec2 { x: 54.0, y: 50.0 },
+                    size: Vec2 { x: 280.0, y: 280.0 },
                 }),
                 &vec![
                     String::from("my go Buffered Robin Nola Alden Line"),
@@ -847,7 +2044,7 @@ fn main() {
             let col_id = insert_text(
                 &mut stage.text_data,
                 &mut stage.text_component,
-                Vec2 { x: 200.0, y: 200.0 },
+                Vec2 { x: 100.0, y: 100.0 },
                 None,
                 &vec![String::from("Old value.")],
             );
@@ -857,7 +2054,10 @@ fn main() {
                 col_id,
                 vec![String::from("________________🐧🐧🐧 New value!")].as_slice(),
             );
-            perform_effects(&mut stage);
+            stage.builtin_columns = stage.text_data.columns.len();
+            // Lean's columns are numbered after main's
+            stage.interp.effects.next_id = stage.builtin_columns as u64;
+            stage.send_event(Event::Init);
             stage
         })
     });
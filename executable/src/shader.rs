@@ -3,31 +3,38 @@ use miniquad::*;
 pub const VERTEX: &str = r#"#version 100
     attribute vec2 in_pos;
     attribute vec2 in_uv;
+    attribute float in_tint;
 
     uniform vec2 offset;
     uniform vec2 window_scale;
 
     varying lowp vec2 texcoord;
+    varying lowp float tint;
 
     // precision highp float;
 
     void main() {
         gl_Position = vec4((window_scale * (in_pos.xy + offset))+vec2(-1,1), 0.0, 1.0);
         texcoord = in_uv;
+        tint = in_tint;
     }"#;
 
 pub const FRAGMENT: &str = r#"#version 100
     varying lowp vec2 texcoord;
+    varying lowp float tint;
 
     uniform sampler2D tex;
 
     precision highp float;
 
+    uniform vec4 color;
+    uniform float opacity;
+
     void main() {
         vec4 texColor = texture2D(tex, texcoord);
 //        if(texColor.a < 0.1)
 //          discard;
-        gl_FragColor = texColor;
+        gl_FragColor = vec4(mix(texColor.rgb, color.rgb, tint), texColor.a * color.a * opacity);
     }"#;
 
 pub const METAL: &str = r#"
@@ -37,20 +44,24 @@ pub const METAL: &str = r#"
 
     struct Uniforms
     {
+        float4 color;
         float2 offset;
         float2 window_scale;
+        float opacity;
     };
 
     struct Vertex
     {
         float2 in_pos   [[attribute(0)]];
         float2 in_uv    [[attribute(1)]];
+        float in_tint   [[attribute(2)]];
     };
 
     struct RasterizerData
     {
         float4 position [[position]];
         float2 uv       [[user(locn0)]];
+        float tint      [[user(locn1)]];
     };
 
     vertex RasterizerData vertexShader(
@@ -61,13 +72,19 @@ pub const METAL: &str = r#"
 
         out.position = float4((uniforms.window_scale * (v.in_pos.xy + uniforms.offset)) + float2(-1.0,1.0), 0.0, 1.0);
         out.uv = v.in_uv;
+        out.tint = v.in_tint;
 
         return out;
     }
 
-    fragment float4 fragmentShader(RasterizerData in [[stage_in]], texture2d<float> tex [[texture(0)]], sampler texSmplr [[sampler(0)]])
+    fragment float4 fragmentShader(
+      RasterizerData in [[stage_in]],
+      constant Uniforms& uniforms [[buffer(0)]],
+      texture2d<float> tex [[texture(0)]],
+      sampler texSmplr [[sampler(0)]])
     {
-        return tex.sample(texSmplr, in.uv);
+        float4 texColor = tex.sample(texSmplr, in.uv);
+        return float4(mix(texColor.rgb, uniforms.color.rgb, in.tint), texColor.a * uniforms.color.a * uniforms.opacity);
     }"#;
 
 pub fn meta() -> ShaderMeta {
@@ -75,15 +92,20 @@ pub fn meta() -> ShaderMeta {
         images: vec!["tex".to_string()],
         uniforms: UniformBlockLayout {
             uniforms: vec![
+                UniformDesc::new("color", UniformType::Float4),
                 UniformDesc::new("offset", UniformType::Float2),
                 UniformDesc::new("window_scale", UniformType::Float2),
+                UniformDesc::new("opacity", UniformType::Float1),
             ],
         },
     }
 }
 
+// color first, so the Metal struct's float4 alignment leaves nothing to pad
 #[repr(C)]
 pub struct Uniforms {
+    pub color: [f32; 4], // the column's theme role, glyph masks are drawn in it
     pub offset: (f32, f32),
     pub window_scale: (f32, f32),
+    pub opacity: f32, // the column's, multiplies the glyphs' alpha
 }
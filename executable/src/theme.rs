@@ -0,0 +1,180 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+// rgba from 0 to 1, written "#rrggbb" or "#rrggbbaa" in theme files.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Rgba(pub [f32; 4]);
+
+impl Rgba {
+    pub fn parse(hex: &str) -> Result<Rgba, String> {
+        let digits = hex.strip_prefix('#').unwrap_or(hex);
+        if !(digits.len() == 6 || digits.len() == 8) || !digits.is_ascii() {
+            return Err(format!(
+                "expected a color like #1a2b3c or #1a2b3cff, got {:?}",
+                hex
+            ));
+        }
+        let mut rgba = [1.0; 4];
+        for (i, channel) in rgba.iter_mut().enumerate().take(digits.len() / 2) {
+            let byte = u8::from_str_radix(&digits[i * 2..i * 2 + 2], 16)
+                .map_err(|_| format!("bad hex in color {:?}", hex))?;
+            *channel = byte as f32 / 255.0;
+        }
+        Ok(Rgba(rgba))
+    }
+
+    const fn rgb(r: u8, g: u8, b: u8) -> Rgba {
+        Rgba([r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0, 1.0])
+    }
+}
+
+impl TryFrom<String> for Rgba {
+    type Error = String;
+    fn try_from(hex: String) -> Result<Rgba, String> {
+        Rgba::parse(&hex)
+    }
+}
+
+impl From<Rgba> for String {
+    fn from(color: Rgba) -> String {
+        let [r, g, b, a] = color.0.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8);
+        format!("#{:02x}{:02x}{:02x}{:02x}", r, g, b, a)
+    }
+}
+
+// What a color is for. Columns draw their text in one, Lean picks it with setColumnRole.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Role {
+    Background,
+    Text,
+    Muted,
+    Accent,
+    Selection,
+    GridLine,
+    Error,
+}
+
+impl Role {
+    // the names theme files use
+    pub fn parse(name: &str) -> Result<Role, String> {
+        match name {
+            "background" => Ok(Role::Background),
+            "text" => Ok(Role::Text),
+            "muted" => Ok(Role::Muted),
+            "accent" => Ok(Role::Accent),
+            "selection" => Ok(Role::Selection),
+            "grid_line" => Ok(Role::GridLine),
+            "error" => Ok(Role::Error),
+            _ => Err(format!(
+                "unknown role {:?}, expected background/text/muted/accent/selection/grid_line/error",
+                name
+            )),
+        }
+    }
+}
+
+// Roles a file leaves out come from the light preset, or dark with "base": "dark".
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Theme {
+    pub background: Rgba,
+    pub text: Rgba,
+    pub muted: Rgba,
+    pub accent: Rgba,
+    pub selection: Rgba,
+    pub grid_line: Rgba,
+    pub error: Rgba,
+}
+
+// Every role optional, filled in from the base preset.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ThemeFile {
+    base: Option<String>,
+    background: Option<Rgba>,
+    text: Option<Rgba>,
+    muted: Option<Rgba>,
+    accent: Option<Rgba>,
+    selection: Option<Rgba>,
+    grid_line: Option<Rgba>,
+    error: Option<Rgba>,
+}
+
+impl Theme {
+    pub fn light() -> Theme {
+        Theme {
+            background: Rgba::rgb(0xff, 0xe6, 0xe6),
+            text: Rgba::rgb(0x10, 0x10, 0x30),
+            muted: Rgba::rgb(0x70, 0x68, 0x78),
+            accent: Rgba::rgb(0x20, 0x50, 0xb0),
+            selection: Rgba([0.2, 0.4, 0.9, 0.3]),
+            grid_line: Rgba::rgb(0xc8, 0xb0, 0xb0),
+            error: Rgba::rgb(0xb0, 0x10, 0x10),
+        }
+    }
+
+    pub fn dark() -> Theme {
+        Theme {
+            background: Rgba::rgb(0x1c, 0x1b, 0x22),
+            text: Rgba::rgb(0xe4, 0xe2, 0xea),
+            muted: Rgba::rgb(0x8a, 0x86, 0x94),
+            accent: Rgba::rgb(0x7a, 0xa8, 0xff),
+            selection: Rgba([0.4, 0.6, 1.0, 0.3]),
+            grid_line: Rgba::rgb(0x3a, 0x38, 0x44),
+            error: Rgba::rgb(0xff, 0x6b, 0x6b),
+        }
+    }
+
+    pub fn preset(name: &str) -> Option<Theme> {
+        match name {
+            "light" => Some(Theme::light()),
+            "dark" => Some(Theme::dark()),
+            _ => None,
+        }
+    }
+
+    // "light", "dark", or the path of a .json or .toml theme file.
+    pub fn load(spec: &str) -> Result<Theme, String> {
+        if let Some(theme) = Theme::preset(spec) {
+            return Ok(theme);
+        }
+        let path = Path::new(spec);
+        let text =
+            std::fs::read_to_string(path).map_err(|e| format!("reading theme {}: {}", spec, e))?;
+        let file: ThemeFile = match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => serde_json::from_str(&text).map_err(|e| e.to_string()),
+            Some("toml") => toml::from_str(&text).map_err(|e| e.to_string()),
+            _ => Err(String::from(
+                "expected light, dark, or a .json or .toml file",
+            )),
+        }
+        .map_err(|e| format!("theme {}: {}", spec, e))?;
+        let base = match &file.base {
+            Some(name) => Theme::preset(name)
+                .ok_or_else(|| format!("theme {}: unknown base {:?}", spec, name))?,
+            None => Theme::light(),
+        };
+        Ok(Theme {
+            background: file.background.unwrap_or(base.background),
+            text: file.text.unwrap_or(base.text),
+            muted: file.muted.unwrap_or(base.muted),
+            accent: file.accent.unwrap_or(base.accent),
+            selection: file.selection.unwrap_or(base.selection),
+            grid_line: file.grid_line.unwrap_or(base.grid_line),
+            error: file.error.unwrap_or(base.error),
+        })
+    }
+
+    pub fn color(&self, role: Role) -> [f32; 4] {
+        match role {
+            Role::Background => self.background,
+            Role::Text => self.text,
+            Role::Muted => self.muted,
+            Role::Accent => self.accent,
+            Role::Selection => self.selection,
+            Role::GridLine => self.grid_line,
+            Role::Error => self.error,
+        }
+        .0
+    }
+}
@@ -0,0 +1,72 @@
+use crate::lean_experiments::gui_api::TimerId;
+use std::collections::BTreeMap;
+
+// Times are miniquad's date::now(), in seconds.
+#[derive(Debug, Clone, Copy)]
+struct Timer {
+    due: f64,
+    every: Option<f64>, // None fires once
+}
+
+// The timers and frame ticks the script asked for. Stage::update delivers them.
+#[derive(Debug, Default)]
+pub struct Timers {
+    timers: BTreeMap<TimerId, Timer>,
+    frame_requested: bool,
+}
+
+impl Timers {
+    // Requesting an id that's already scheduled reschedules it.
+    pub fn request(&mut self, id: TimerId, now: f64, delay: f64, every: Option<f64>) {
+        let due = now + delay.max(0.0);
+        self.timers.insert(id, Timer { due, every });
+    }
+
+    pub fn cancel(&mut self, id: TimerId) {
+        self.timers.remove(&id);
+    }
+
+    pub fn request_frame(&mut self) {
+        self.frame_requested = true;
+    }
+
+    // Once per requestFrame, however many were made since the last frame.
+    pub fn take_frame(&mut self) -> bool {
+        std::mem::take(&mut self.frame_requested)
+    }
+
+    // The timers due by now, soonest first. Only these fire this frame, so one requested while
+    // they're delivered waits for the next, even with no delay.
+    pub fn due(&self, now: f64) -> Vec<TimerId> {
+        let mut due: Vec<(f64, TimerId)> = self
+            .timers
+            .iter()
+            .filter(|(_, timer)| timer.due <= now)
+            .map(|(id, timer)| (timer.due, *id))
+            .collect();
+        due.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+        due.into_iter().map(|(_, id)| id).collect()
+    }
+
+    // False when the timer was cancelled or rescheduled by an earlier one delivered this frame.
+    // A repeating timer that fell behind, e.g. while the window was hidden, fires once and
+    // carries on from now rather than catching up.
+    pub fn fire(&mut self, id: TimerId, now: f64) -> bool {
+        let timer = match self.timers.get_mut(&id) {
+            Some(timer) if timer.due <= now => timer,
+            _ => return false,
+        };
+        match timer.every {
+            Some(every) => timer.due = (timer.due + every).max(now),
+            None => {
+                self.timers.remove(&id);
+            }
+        }
+        true
+    }
+
+    pub fn clear(&mut self) {
+        self.timers.clear();
+        self.frame_requested = false;
+    }
+}
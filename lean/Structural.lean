@@ -1,3 +1,6 @@
+import Structural.Gui
+import Structural.Layout
+
 @[export struct_hello]
 def structHello := "world from here"
 
@@ -38,56 +41,137 @@ def leanUseIOStringCallback (a : String -> IO String) : IO String := do
 
 -- @[export leans_other_answer]
 -- def leansOtherAnswer : IO UInt8 := rustsAnswer
-inductive Event where
-  | init : Event -- called immediately after leanOnInit with the state provided from that
-  | char : Event
-  | up : Event
-  | down : Event
-  deriving Repr
-
 structure State where
   text : String
+  splitters : Structural.Splitters := [] -- as dragged, the layout is built from them
   deriving Repr
 
 @[export lean_use_on_event]
-def leanUseOnEvent(on_event : Event -> IO Uint8) (clear_effects : Event -> IO Uint8) : IO Unit := do
+def leanUseOnEvent(on_event : Structural.Event -> IO Uint8) (clear_effects : Structural.Event -> IO Uint8) : IO Unit := do
   IO.println "ok, starting"
-  _ <- on_event Event.up
-  _ <- clear_effects Event.down
-  _ <- on_event Event.char
+  _ <- on_event Structural.Event.init
+  _ <- clear_effects (Structural.Event.key "Down" true)
+  _ <- on_event (Structural.Event.char 'a')
   IO.println "ok, done"
 
-@[export lean_on_event]
-def leanOnEvent
-    (event : Event)
-    (state : State)
-    (char : UInt32)
-    (setAppState : State -> IO Unit)
-    (freshColumn : Float -> Float -> IO UInt64)
-    (pushLine : UInt64 -> String -> IO Unit)
-    (resetText : UInt64 -> IO Unit)
-    (setClip : UInt64 -> Float -> Float -> Float -> Float -> IO Unit)
-    (removeClip : UInt64 -> IO Unit)
-    (animate : UInt64 -> Float -> Float -> Float -> IO Unit)
-    (quit : IO Unit)
-    : IO Unit := do
-  let cid <- freshColumn 111.0 222.0
-  let cid2 <- freshColumn 333.0 444.0
+namespace Structural.GuiM
+
+variable {σ : Type}
+
+-- A column of plain text lines, returning its id.
+def column (x y : Float) (lines : List String) : GuiM σ ColumnId := do
+  let col ← freshColumn x y
+  for line in lines do
+    pushLine col line
+  return col
+
+end Structural.GuiM
+
+-- The columns where Lean put them on the left, the grid on the right of a splitter.
+def demoLayout (splitters : Structural.Splitters) : Structural.Layout :=
+  .hsplitter "main" (splitters.ratio "main" 0.45) 200.0 .empty (.pad 50.0 50.0 50.0 0.0 .grid)
+
+open Structural Structural.GuiM in
+def onEvent (event : Event) (state : State) : GuiM State Unit := do
+  -- kept in the state, the next layout would put the divider back otherwise
+  if let Event.splitter name ratio := event then
+    let splitters := state.splitters.set name ratio
+    setAppState {state with splitters := splitters}
+    layout (demoLayout splitters)
+    return ()
+  -- the demo below makes new columns every time, so keep it to typing
+  match event with
+  | Event.init | Event.char _ => pure ()
+  | _ => return ()
+  let cid <- freshColumn 55.5 111.0
+  let cid2 <- freshColumn 166.5 222.0
   pushLine cid "Line1"
   pushLine cid2 "Line2A"
   pushLine cid2 "Line2B"
-  let the_char := Char.ofNat char.toNat
   let next_text := match event with
-                   | Event.char => state.text.push the_char
+                   | Event.char c => state.text.push c
                    | _ => state.text
-  setAppState {text := next_text}
+  setAppState {state with text := next_text}
   pushLine cid2 next_text
-  let cid3 <- freshColumn 444.0 555.0
+  let cid3 <- freshColumn 222.0 277.5
   pushLine cid3 "Wontseeme"
   resetText cid3
+  match event with
+  | Event.init => do
+    let amounts <- freshColumn 450.0 50.0
+    setHeader amounts "amount"
+    setFormat amounts "decimals=2"
+    pushCell amounts "float" "1234567.891"
+    pushCell amounts "int" "-42"
+    pushCell amounts "null" ""
+    setFooter amounts "count sum min max avg distinct"
+    let notes <- freshColumn 700.0 50.0
+    setHeader notes "note"
+    setFormat notes "width=200"
+    pushCell notes "text" "a note long enough to need truncating"
+    pushCell notes "timestamp" "1700000000"
+    pushCell notes "bytes" "deadbeef00112233445566"
+    setGridView 450.0 50.0 500.0 200.0 1 1
+    layout (demoLayout state.splitters)
+  -- Lean sorts and filters, here by amount ascending and without the null
+  | Event.char 's' => setShownRows #[1, 0]
+  | Event.char 'x' => do
+    exportGrid "csv" "grid.csv"
+    exportGrid "jsonl" "grid.jsonl"
+    exportGrid "markdown" "grid.md"
+  | _ => pure ()
+
+@[export lean_on_event]
+def leanOnEvent (event : Structural.Event) (state : State) (gui : Structural.Gui State) : IO Unit :=
+  (onEvent event state).run gui
 --  IO.println s!"ok, called leanOnEvent. event: {repr event} with state: {repr state} id: {cid},{cid2} char: {char}={the_char}"
 
+-- Doesn't compile if leanOnEvent drifts from the signature Rust calls it with.
+example : Structural.LeanOnEvent State := leanOnEvent
+
 -- maybe think of better name, like initial_state, to distinguish from the on init event
 @[export lean_on_init]
 def leanOnInit : IO State := do
   return {text := "init"}
+
+-- A line per splitter, its ratio in millionths since there's no parsing a Float back.
+private def saveSplitter : String × Float → String
+  | (name, ratio) => s!"splitter {(ratio * 1000000.0).round.toUInt64} {name}\n"
+
+private def loadSplitter (line : String) : Option (String × Float) :=
+  match line.splitOn " " with
+  | "splitter" :: millionths :: name@(_ :: _) =>
+    millionths.toNat?.map fun n => (" ".intercalate name, n.toFloat / 1000000.0)
+  | _ => none
+
+-- The splitter lines at the start of saved, and the rest.
+private partial def loadSplitters (saved : String) (splitters : Structural.Splitters) :
+    Structural.Splitters × String :=
+  match saved.splitOn "\n" with
+  | line :: rest@(_ :: _) =>
+    match loadSplitter line with
+    | some splitter => loadSplitters ("\n".intercalate rest) (splitters ++ [splitter])
+    | none => (splitters, saved)
+  | _ => (splitters, saved)
+
+-- The splitters then the text, saved on quit and every so often, and restored on launch. A
+-- state saved before there were splitters is all text.
+@[export lean_save_state]
+def leanSaveState (state : State) : IO String :=
+  let splitters := state.splitters.filter fun (name, _) => !name.contains '\n'
+  pure (String.join (splitters.map saveSplitter) ++ state.text)
+
+@[export lean_load_state]
+def leanLoadState (saved : String) : IO State :=
+  let (splitters, text) := loadSplitters saved []
+  pure {text := text, splitters := splitters}
+
+example : Structural.LeanSaveState State := leanSaveState
+example : Structural.LeanLoadState State := leanLoadState
+
+-- Called with the running state when a rebuilt libStructural is hot reloaded. Only sound while
+-- State keeps its shape: after changing it, take the old shape here, or remove this to start
+-- over from leanOnInit.
+@[export lean_migrate_state]
+def leanMigrateState (old : State) : IO State :=
+  pure {text := old.text, splitters := old.splitters}
@@ -0,0 +1,155 @@
+-- Generated by `tabularasa --emit-lean` from EFFECTS in gui_api.rs. Don't edit by hand.
+
+/-!
+Typed bindings for the effects Rust hands to `leanOnEvent`.
+
+Rust builds the `Gui` once and hands the same one to every event (see `mk_gui` in
+gui_api.rs), with the closures in field order. Scripts run in `GuiM` and call the
+wrappers below instead of threading each closure through by hand.
+-/
+
+namespace Structural
+
+abbrev ColumnId := UInt64
+abbrev TimerId := UInt64
+
+inductive MouseKind where
+  | down | up | move
+  deriving Repr, BEq
+
+inductive Event where
+  | init -- sent once, right after leanOnInit
+  | char (c : Char)
+  | key (name : String) (down : Bool) -- name is miniquad's KeyCode, e.g. "Up", "Enter", "A"
+  | mouse (kind : MouseKind) (x y : Float) (button : UInt8) -- 0 left, 1 right, 2 middle, 3 other
+  | resize (width height : Float) -- the window's new size, positions are all logical pixels
+  | tick (time : Float) -- the frame's time in seconds, once per requestFrame
+  | dataReady (col : ColumnId) -- a grid column's footer summary was recomputed
+  | timer (id : TimerId) -- from requestTimer
+  | splitter (name : String) (ratio : Float) -- a splitter was dragged and let go, keep ratio in the layout
+  deriving Repr
+
+structure Gui (σ : Type) where
+  setAppState : σ → IO Unit
+  freshColumn : Float → Float → IO ColumnId
+  pushLine : ColumnId → String → IO Unit
+  resetText : ColumnId → IO Unit
+  setClip : ColumnId → Float → Float → Float → Float → IO Unit
+  removeClip : ColumnId → IO Unit
+  animate : ColumnId → Float → Float → Float → String → IO Unit -- moves the column from wherever it is now, easing is linear/cubic-in/cubic-out/cubic/ease-in-out/spring
+  animateAfter : ColumnId → Float → Float → Float → String → IO Unit -- like animate, once the column's animations so far are done
+  animateClip : ColumnId → Float → Float → Float → Float → Float → String → IO Unit -- x y w h, from the column's current clip, an unclipped one jumps straight there
+  animateClipAfter : ColumnId → Float → Float → Float → Float → Float → String → IO Unit -- like animate_clip, once the column's clip animations so far are done
+  animateColor : ColumnId → String → Float → String → IO Unit -- to a color like "#1a2b3c" or "#1a2b3cff", from the one its text is drawn in now
+  animateColorAfter : ColumnId → String → Float → String → IO Unit -- like animate_color, once the column's color animations so far are done
+  animateScroll : Float → Float → Float → String → IO Unit -- scrolls the grid view to x y, clamped to its contents
+  animateScrollAfter : Float → Float → Float → String → IO Unit -- like animate_scroll, once the scrolling so far is done
+  setOpacity : ColumnId → Float → IO Unit -- 0 transparent to 1 opaque, straight away
+  fade : ColumnId → Float → Float → String → IO Unit -- to opacity over duration, from the column's current one
+  fadeAfter : ColumnId → Float → Float → String → IO Unit -- like fade, once the column's fades so far are done
+  setColumnRole : ColumnId → String → IO Unit -- the theme color its text is drawn in: text/muted/accent/selection/grid_line/error/background
+  setTheme : String → IO Unit -- "light", "dark", or the path of a .json or .toml theme file
+  pushCell : ColumnId → String → String → IO Unit -- kind is one of null/int/float/text/timestamp/bytes
+  setFormat : ColumnId → String → IO Unit -- e.g. "decimals=2 width=400", see CellFormat::parse
+  setHeader : ColumnId → String → IO Unit
+  setShownRows : Array UInt64 → IO Unit -- the rows the grid shows, as indices into the pushed cells, in the order Lean sorted them and leaving out the ones it filtered away; drawing and exports follow it
+  showAllRows : IO Unit -- back to every row in the order pushed
+  setSelection : Array UInt64 → Array ColumnId → IO Unit -- what exportGrid writes, out of the shown rows and the grid columns, empty selects them all
+  exportGrid : String → String → IO Unit -- format, path; the selection in the order shown
+  setFooter : ColumnId → String → IO Unit -- e.g. "count sum avg", "" hides it
+  setGridView : Float → Float → Float → Float → UInt64 → UInt64 → IO Unit -- x y w h frozenRows pinnedColumns
+  setLayout : String → IO Unit -- e.g. "hsplit(250 #3, 1fr vsplit(1fr pad(8, #4), 120 grid))", see Layout.lean to build one, redone on resize and only moves what changed, "" turns it off
+  requestTimer : Float → Float → IO TimerId -- fires Event.timer after delay seconds, then every `every` seconds unless that's 0
+  cancelTimer : TimerId → IO Unit
+  requestFrame : IO Unit -- one Event.tick on the next frame, ask again from it to keep them coming
+  quit : IO Unit
+
+abbrev GuiM (σ : Type) := ReaderT (Gui σ) IO
+
+-- What `leanOnEvent` must be, for the script's State σ.
+abbrev LeanOnEvent (σ : Type) := Event → σ → Gui σ → IO Unit
+
+-- Optional, `leanSaveState` and `leanLoadState` keep the state between launches in a format of
+-- the script's choosing.
+abbrev LeanSaveState (σ : Type) := σ → IO String
+abbrev LeanLoadState (σ : Type) := String → IO σ
+
+-- Checked against the Rust side when libStructural is loaded.
+@[export lean_gui_abi]
+def guiAbi (_ : Unit) : String :=
+  "inductive MouseKind where\n  | down | up | move\n  deriving Repr, BEq\n\ninductive Event where\n  | init\n  | char (c : Char)\n  | key (name : String) (down : Bool)\n  | mouse (kind : MouseKind) (x y : Float) (button : UInt8)\n  | resize (width height : Float)\n  | tick (time : Float)\n  | dataReady (col : ColumnId)\n  | timer (id : TimerId)\n  | splitter (name : String) (ratio : Float)\n  deriving Repr\nleanOnEvent : Event → σ → Gui σ → IO Unit\nsetAppState : σ → IO Unit\nfreshColumn : Float → Float → IO ColumnId\npushLine : ColumnId → String → IO Unit\nresetText : ColumnId → IO Unit\nsetClip : ColumnId → Float → Float → Float → Float → IO Unit\nremoveClip : ColumnId → IO Unit\nanimate : ColumnId → Float → Float → Float → String → IO Unit\nanimateAfter : ColumnId → Float → Float → Float → String → IO Unit\nanimateClip : ColumnId → Float → Float → Float → Float → Float → String → IO Unit\nanimateClipAfter : ColumnId → Float → Float → Float → Float → Float → String → IO Unit\nanimateColor : ColumnId → String → Float → String → IO Unit\nanimateColorAfter : ColumnId → String → Float → String → IO Unit\nanimateScroll : Float → Float → Float → String → IO Unit\nanimateScrollAfter : Float → Float → Float → String → IO Unit\nsetOpacity : ColumnId → Float → IO Unit\nfade : ColumnId → Float → Float → String → IO Unit\nfadeAfter : ColumnId → Float → Float → String → IO Unit\nsetColumnRole : ColumnId → String → IO Unit\nsetTheme : String → IO Unit\npushCell : ColumnId → String → String → IO Unit\nsetFormat : ColumnId → String → IO Unit\nsetHeader : ColumnId → String → IO Unit\nsetShownRows : Array UInt64 → IO Unit\nshowAllRows : IO Unit\nsetSelection : Array UInt64 → Array ColumnId → IO Unit\nexportGrid : String → String → IO Unit\nsetFooter : ColumnId → String → IO Unit\nsetGridView : Float → Float → Float → Float → UInt64 → UInt64 → IO Unit\nsetLayout : String → IO Unit\nrequestTimer : Float → Float → IO TimerId\ncancelTimer : TimerId → IO Unit\nrequestFrame : IO Unit\nquit : IO Unit"
+
+namespace GuiM
+
+variable {σ : Type}
+
+def setAppState (s : σ) : GuiM σ Unit := do
+  (← read).setAppState s
+def freshColumn (x : Float) (y : Float) : GuiM σ ColumnId := do
+  (← read).freshColumn x y
+def pushLine (col : ColumnId) (line : String) : GuiM σ Unit := do
+  (← read).pushLine col line
+def resetText (col : ColumnId) : GuiM σ Unit := do
+  (← read).resetText col
+def setClip (col : ColumnId) (x : Float) (y : Float) (w : Float) (h : Float) : GuiM σ Unit := do
+  (← read).setClip col x y w h
+def removeClip (col : ColumnId) : GuiM σ Unit := do
+  (← read).removeClip col
+def animate (col : ColumnId) (x : Float) (y : Float) (duration : Float) (easing : String := "linear") : GuiM σ Unit := do
+  (← read).animate col x y duration easing
+def animateAfter (col : ColumnId) (x : Float) (y : Float) (duration : Float) (easing : String := "linear") : GuiM σ Unit := do
+  (← read).animateAfter col x y duration easing
+def animateClip (col : ColumnId) (x : Float) (y : Float) (w : Float) (h : Float) (duration : Float) (easing : String := "linear") : GuiM σ Unit := do
+  (← read).animateClip col x y w h duration easing
+def animateClipAfter (col : ColumnId) (x : Float) (y : Float) (w : Float) (h : Float) (duration : Float) (easing : String := "linear") : GuiM σ Unit := do
+  (← read).animateClipAfter col x y w h duration easing
+def animateColor (col : ColumnId) (color : String) (duration : Float) (easing : String := "linear") : GuiM σ Unit := do
+  (← read).animateColor col color duration easing
+def animateColorAfter (col : ColumnId) (color : String) (duration : Float) (easing : String := "linear") : GuiM σ Unit := do
+  (← read).animateColorAfter col color duration easing
+def animateScroll (x : Float) (y : Float) (duration : Float) (easing : String := "linear") : GuiM σ Unit := do
+  (← read).animateScroll x y duration easing
+def animateScrollAfter (x : Float) (y : Float) (duration : Float) (easing : String := "linear") : GuiM σ Unit := do
+  (← read).animateScrollAfter x y duration easing
+def setOpacity (col : ColumnId) (opacity : Float) : GuiM σ Unit := do
+  (← read).setOpacity col opacity
+def fade (col : ColumnId) (to : Float) (duration : Float) (easing : String := "linear") : GuiM σ Unit := do
+  (← read).fade col to duration easing
+def fadeAfter (col : ColumnId) (to : Float) (duration : Float) (easing : String := "linear") : GuiM σ Unit := do
+  (← read).fadeAfter col to duration easing
+def setColumnRole (col : ColumnId) (role : String) : GuiM σ Unit := do
+  (← read).setColumnRole col role
+def setTheme (theme : String) : GuiM σ Unit := do
+  (← read).setTheme theme
+def pushCell (col : ColumnId) (kind : String) (raw : String) : GuiM σ Unit := do
+  (← read).pushCell col kind raw
+def setFormat (col : ColumnId) (spec : String) : GuiM σ Unit := do
+  (← read).setFormat col spec
+def setHeader (col : ColumnId) (name : String) : GuiM σ Unit := do
+  (← read).setHeader col name
+def setShownRows (rows : Array UInt64) : GuiM σ Unit := do
+  (← read).setShownRows rows
+def showAllRows : GuiM σ Unit := do
+  (← read).showAllRows
+def setSelection (rows : Array UInt64) (columns : Array ColumnId) : GuiM σ Unit := do
+  (← read).setSelection rows columns
+def exportGrid (format : String) (path : String) : GuiM σ Unit := do
+  (← read).exportGrid format path
+def setFooter (col : ColumnId) (spec : String) : GuiM σ Unit := do
+  (← read).setFooter col spec
+def setGridView (x : Float) (y : Float) (w : Float) (h : Float) (frozenRows : UInt64) (pinnedColumns : UInt64) : GuiM σ Unit := do
+  (← read).setGridView x y w h frozenRows pinnedColumns
+def setLayout (spec : String) : GuiM σ Unit := do
+  (← read).setLayout spec
+def requestTimer (delay : Float) (every : Float := 0) : GuiM σ TimerId := do
+  (← read).requestTimer delay every
+def cancelTimer (timer : TimerId) : GuiM σ Unit := do
+  (← read).cancelTimer timer
+def requestFrame : GuiM σ Unit := do
+  (← read).requestFrame
+def quit : GuiM σ Unit := do
+  (← read).quit
+
+end GuiM
+
+end Structural
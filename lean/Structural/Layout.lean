@@ -0,0 +1,71 @@
+import Structural.Gui
+
+namespace Structural
+
+-- Along the parent split: fixed logical pixels, or a share of what the fixed ones leave.
+inductive Size where
+  | px (n : Float)
+  | fr (n : Float)
+  deriving Repr, Inhabited
+
+-- Describe the whole window on every event if it's easier, Rust only moves what changed.
+inductive Layout where
+  | hsplit (items : List (Size × Layout)) -- side by side
+  | vsplit (items : List (Size × Layout)) -- top to bottom
+  | stack (layers : List Layout)
+  | scroll (x y : Float) (child : Layout) -- contents moved left and up by x y, clipped
+  | pad (top right bottom left : Float) (child : Layout)
+  -- two panes with a draggable divider, ratio is the first's share, neither goes below min
+  | hsplitter (name : String) (ratio min : Float) (first second : Layout) -- side by side
+  | vsplitter (name : String) (ratio min : Float) (first second : Layout)
+  | column (col : ColumnId)
+  | grid -- the grid view
+  | empty
+  deriving Repr, Inhabited
+
+-- The spec setLayout parses, negative sizes count as 0. Scroll offsets keep their sign.
+private def num (n : Float) : String := toString (max n 0)
+
+def Size.spec : Size → String
+  | .px n => num n
+  | .fr n => num n ++ "fr"
+
+namespace Layout
+
+partial def spec : Layout → String
+  | .hsplit items => "hsplit(" ++ ", ".intercalate (items.map fun (s, l) => s.spec ++ " " ++ spec l) ++ ")"
+  | .vsplit items => "vsplit(" ++ ", ".intercalate (items.map fun (s, l) => s.spec ++ " " ++ spec l) ++ ")"
+  | .stack layers => "stack(" ++ ", ".intercalate (layers.map spec) ++ ")"
+  | .scroll x y child => s!"scroll({x} {y}, {spec child})"
+  | .pad t r b l child => s!"pad({num t} {num r} {num b} {num l}, {spec child})"
+  | .hsplitter name ratio min a b => s!"hsplitter({name.quote} {num ratio} {num min}, {spec a}, {spec b})"
+  | .vsplitter name ratio min a b => s!"vsplitter({name.quote} {num ratio} {num min}, {spec a}, {spec b})"
+  | .column col => s!"#{col}"
+  | .grid => "grid"
+  | .empty => "_"
+
+end Layout
+
+-- Splitter ratios by name, for the app state. Event.splitter says where one was dragged to, set
+-- it here and build the layout from ratio, or the divider goes back on the next layout change.
+abbrev Splitters := List (String × Float)
+
+def Splitters.ratio (s : Splitters) (name : String) (default : Float) : Float :=
+  (s.lookup name).getD default
+
+def Splitters.set (s : Splitters) (name : String) (ratio : Float) : Splitters :=
+  (name, ratio) :: s.filter (·.1 != name)
+
+namespace GuiM
+
+variable {σ : Type}
+
+-- Places the columns and the grid view, and again on every resize.
+def layout (l : Layout) : GuiM σ Unit := setLayout l.spec
+
+-- Back to the positions columns were made with.
+def clearLayout : GuiM σ Unit := setLayout ""
+
+end GuiM
+
+end Structural
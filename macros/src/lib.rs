@@ -0,0 +1,261 @@
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::{format_ident, quote};
+use syn::{
+    parse_macro_input, spanned::Spanned, Expr, FnArg, GenericArgument, Ident, ItemFn, Lit, Meta,
+    Pat, PathArguments, ReturnType, Type,
+};
+
+// Turns a plain Rust function into a Lean effect:
+//
+//     /// shown as a comment on the Gui field
+//     #[lean_effect]
+//     pub fn push_line(interp: &mut Interpreter, col: ColID, line: String) { ... }
+//
+// generates the `PushLine` closure type, a `push_line` extern "C" shim taking the closed over
+// external object, the boxed arguments and the IO world, `mk_push_line` closing over a given
+// external, and a `PUSH_LINE` EffectDecl describing its Lean signature for the registry. The
+// shim takes ownership of every argument as a LeanRef, converts it with FromLean, and wraps the
+// result with io_result_ok. An argument of the wrong type is an IO error, never a panic.
+// The first parameter must be `&mut Interpreter`, it's what the closure closes over.
+// `#[lean_default(0)]` on a parameter gives the GuiM wrapper a default for it.
+#[proc_macro_attribute]
+pub fn lean_effect(attr: TokenStream, item: TokenStream) -> TokenStream {
+    if !attr.is_empty() {
+        return syn::Error::new(Span::call_site(), "lean_effect takes no arguments")
+            .to_compile_error()
+            .into();
+    }
+    let func = parse_macro_input!(item as ItemFn);
+    match expand(func) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn expand(mut func: ItemFn) -> syn::Result<proc_macro2::TokenStream> {
+    let vis = &func.vis;
+    let name = &func.sig.ident;
+    let mut inputs = func.sig.inputs.iter_mut();
+
+    let (interp_arg, interp_ty) = match inputs.next() {
+        Some(FnArg::Typed(arg)) => match &*arg.ty {
+            Type::Reference(r) if r.mutability.is_some() => (arg.pat.clone(), (*r.elem).clone()),
+            ty => return Err(syn::Error::new(ty.span(), "expected `&mut Interpreter`")),
+        },
+        _ => {
+            return Err(syn::Error::new(
+                func.sig.span(),
+                "a lean effect takes the interpreter as its first argument",
+            ))
+        }
+    };
+
+    let mut args: Vec<Ident> = Vec::new();
+    let mut tys: Vec<Type> = Vec::new();
+    let mut params = Vec::new();
+    for input in inputs {
+        match input {
+            FnArg::Typed(arg) => match &*arg.pat {
+                Pat::Ident(p) => {
+                    let lean_name = lower_camel_case(&p.ident.to_string());
+                    let lean_type = lean_type(&arg.ty)?;
+                    let default = take_default(&mut arg.attrs)?;
+                    let default = match default {
+                        Some(d) => quote!(Some(#d)),
+                        None => quote!(None),
+                    };
+                    params.push(quote! {
+                        crate::lean_experiments::gui_api::EffectParam {
+                            name: #lean_name,
+                            lean_type: #lean_type,
+                            default: #default,
+                        }
+                    });
+                    args.push(p.ident.clone());
+                    tys.push((*arg.ty).clone());
+                }
+                pat => return Err(syn::Error::new(pat.span(), "expected a plain argument name")),
+            },
+            FnArg::Receiver(r) => return Err(syn::Error::new(r.span(), "effects can't take self")),
+        }
+    }
+
+    let ffi = quote!(crate::lean_experiments);
+    // the closed over interpreter, the arguments, and the IO world
+    let arity = (args.len() + 2) as u16;
+    let lean_args = args.iter().map(|_| quote!(*mut #ffi::LeanObject));
+    let type_name = Ident::new(&upper_camel_case(&name.to_string()), name.span());
+    let const_name = Ident::new(&name.to_string().to_uppercase(), name.span());
+    let mk_name = format_ident!("mk_{}", name);
+    let body_name = format_ident!("{}_body", name);
+    let (output, lean_result) = match &func.sig.output {
+        ReturnType::Default => (quote!(()), String::from("Unit")),
+        ReturnType::Type(_, ty) => (quote!(#ty), lean_type(ty)?),
+    };
+    let lean_name = lower_camel_case(&name.to_string());
+    let doc = match doc_comment(&func.attrs) {
+        Some(d) => quote!(Some(#d)),
+        None => quote!(None),
+    };
+    let block = &func.block;
+
+    Ok(quote! {
+        #vis type #type_name = extern "C" fn(
+            *mut #ffi::LeanObject,
+            #(#lean_args,)*
+            *mut #ffi::LeanObject,
+        ) -> *mut #ffi::LeanObject;
+
+        #vis extern "C" fn #name(
+            interp: *mut #ffi::LeanObject,
+            #(#args: *mut #ffi::LeanObject,)*
+            _io: *mut #ffi::LeanObject,
+        ) -> *mut #ffi::LeanObject {
+            // one argument per parameter of the Lean field, however many that is
+            #[allow(clippy::too_many_arguments)]
+            fn #body_name(#interp_arg: &mut #interp_ty, #(#args: #tys),*) -> #output #block
+
+            unsafe {
+                // everything is adopted before anything is checked, so a bad argument still
+                // releases the ones after it
+                let ext = #ffi::lean_ref::LeanRef::<#ffi::lean_ref::Any>::from_owned(interp);
+                #(
+                    let #args = #ffi::lean_ref::LeanRef::<#ffi::lean_ref::Any>::from_owned(#args);
+                )*
+                let converted = (move || -> Result<_, String> {
+                    let ext = ext
+                        .and_then(|r| r.downcast::<#ffi::lean_ref::External>())
+                        .map_err(|e| format!("expected the interpreter, {}", e))?;
+                    #(
+                        let #args = #args
+                            .and_then(|r| r.downcast::<<#tys as #ffi::lean_ref::FromLean>::Lean>())
+                            .and_then(<#tys as #ffi::lean_ref::FromLean>::from_lean)
+                            .map_err(|e| format!("argument `{}`, {}", stringify!(#args), e))?;
+                    )*
+                    Ok((ext, #(#args,)*))
+                })();
+                match converted {
+                    Ok((ext, #(#args,)*)) => {
+                        let result = #body_name(&mut *ext.data::<#interp_ty>(), #(#args),*);
+                        #ffi::lean_ref::io_result_ok(result)
+                    }
+                    // Lean sees a failed IO action, which leanOnEvent hands back to report_error
+                    Err(e) => #ffi::lean_ref::io_result_error(&format!(
+                        "{}: {}",
+                        stringify!(#name),
+                        e
+                    )),
+                }
+            }
+        }
+
+        // takes ownership of a reference to the interpreter's external
+        #vis fn #mk_name(
+            interp: *mut #ffi::LeanExternalObject,
+        ) -> *mut #ffi::Closure<#type_name> {
+            #ffi::mk_closure_2(#name, interp, #arity)
+        }
+
+        #vis const #const_name: #ffi::gui_api::EffectDecl = #ffi::gui_api::EffectDecl {
+            name: #lean_name,
+            params: &[#(#params),*],
+            result: #lean_result,
+            doc: #doc,
+            mk: |interp| #mk_name(interp) as *mut #ffi::LeanObject,
+        };
+    })
+}
+
+// Only the types FromLean/IntoLean know about, by name, so a ColID shows up as a ColumnId.
+fn lean_type(ty: &Type) -> syn::Result<String> {
+    let unknown = || syn::Error::new(ty.span(), "no Lean type for this effect argument");
+    match ty {
+        Type::Tuple(t) if t.elems.is_empty() => Ok(String::from("Unit")),
+        Type::Path(p) => {
+            let last = p.path.segments.last().ok_or_else(unknown)?;
+            match last.ident.to_string().as_str() {
+                "f32" | "f64" => Ok(String::from("Float")),
+                "u64" | "usize" => Ok(String::from("UInt64")),
+                "ColID" => Ok(String::from("ColumnId")),
+                "Vec" => match &last.arguments {
+                    PathArguments::AngleBracketed(args) => match args.args.first() {
+                        Some(GenericArgument::Type(item)) => {
+                            Ok(format!("Array {}", lean_type(item)?))
+                        }
+                        _ => Err(unknown()),
+                    },
+                    _ => Err(unknown()),
+                },
+                "TimerId" => Ok(String::from("TimerId")),
+                "String" => Ok(String::from("String")),
+                // the app state, whatever the script's State is
+                "LeanRef" => Ok(String::from("σ")),
+                _ => Err(unknown()),
+            }
+        }
+        _ => Err(unknown()),
+    }
+}
+
+fn take_default(attrs: &mut Vec<syn::Attribute>) -> syn::Result<Option<String>> {
+    let mut default = None;
+    let mut err = None;
+    attrs.retain(|attr| {
+        if !attr.path().is_ident("lean_default") {
+            return true;
+        }
+        match attr.parse_args::<Expr>() {
+            Ok(expr) => default = Some(quote!(#expr).to_string()),
+            Err(e) => err = Some(e),
+        }
+        false
+    });
+    match err {
+        Some(e) => Err(e),
+        None => Ok(default),
+    }
+}
+
+fn doc_comment(attrs: &[syn::Attribute]) -> Option<String> {
+    let lines: Vec<String> = attrs
+        .iter()
+        .filter_map(|attr| match &attr.meta {
+            Meta::NameValue(nv) if nv.path.is_ident("doc") => match &nv.value {
+                Expr::Lit(lit) => match &lit.lit {
+                    Lit::Str(s) => Some(s.value().trim().to_owned()),
+                    _ => None,
+                },
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect();
+    if lines.is_empty() {
+        None
+    } else {
+        Some(lines.join(" "))
+    }
+}
+
+fn upper_camel_case(snake: &str) -> String {
+    snake
+        .split('_')
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(c) => c.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
+
+fn lower_camel_case(snake: &str) -> String {
+    let upper = upper_camel_case(snake);
+    let mut chars = upper.chars();
+    match chars.next() {
+        Some(c) => c.to_lowercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}